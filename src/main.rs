@@ -1,41 +1,147 @@
 use benchmark_counters::StopWatch;
-use chrono::Utc;
+use chrono::format::{Parsed, StrftimeItems};
+use chrono::{DateTime, Datelike, Local, Timelike, Utc};
 use config::Config;
 use crossterm::{
-    event::{self, Event, KeyCode},
+    event::{self, Event, KeyCode, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use std::cmp::max;
+use std::cell::Cell;
+use std::cmp::{max, min};
+use std::collections::VecDeque;
 use std::error::Error;
+use std::fmt;
 use std::fs::OpenOptions;
 use std::io;
-use std::io::Write;
-use std::path::Path;
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::time::{Duration, Instant};
 use structopt::StructOpt;
 use tui::{
     backend::{Backend, CrosstermBackend},
-    layout::{Constraint, Direction, Layout},
+    buffer::Buffer,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Span, Spans, Text},
-    widgets::{Block, Borders, Paragraph, Wrap},
+    widgets::{Block, Borders, Clear, Paragraph, Widget, Wrap},
     Frame, Terminal,
 };
+use unicode_width::UnicodeWidthStr;
 
-const ACTIVE_COLOR: Color = Color::Cyan;
-const DONE_COLOR: Color = Color::Green;
-const WARNING_COLOR: Color = Color::Yellow;
-const DANGER_COLOR: Color = Color::Red;
-const PASSIVE_COLOR: Color = Color::Gray;
+/// Colors of the user interface
+#[derive(Clone)]
+struct Theme {
+    name: String,
+    active: Color,
+    done: Color,
+    warning: Color,
+    danger: Color,
+    passive: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            name: "default".to_string(),
+            active: Color::Cyan,
+            done: Color::Green,
+            warning: Color::Yellow,
+            danger: Color::Red,
+            passive: Color::Gray,
+        }
+    }
+}
+
+impl Theme {
+    /// Reads a theme from an entry of the `themes` config.
+    /// Colors which are not set are taken from the default theme.
+    fn from_table(table: &config::Map<String, config::Value>) -> Theme {
+        let default = Theme::default();
+        let get_color = |key: &str, default: Color| {
+            table
+                .get(key)
+                .and_then(|value| value.clone().into_string().ok())
+                .and_then(|name| parse_color(&name))
+                .unwrap_or(default)
+        };
+        Theme {
+            name: table
+                .get("name")
+                .and_then(|value| value.clone().into_string().ok())
+                .unwrap_or_else(|| "unnamed".to_string()),
+            active: get_color("active", default.active),
+            done: get_color("done", default.done),
+            warning: get_color("warning", default.warning),
+            danger: get_color("danger", default.danger),
+            passive: get_color("passive", default.passive),
+        }
+    }
+}
+
+/// Parses a color name like "cyan" or "lightred" or a hex color like "#ff8800"
+fn parse_color(name: &str) -> Option<Color> {
+    let name = name.to_lowercase();
+    if let Some(hex) = name.strip_prefix('#') {
+        let value = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == 6)?;
+        return Some(Color::Rgb(
+            (value >> 16) as u8,
+            (value >> 8) as u8,
+            value as u8,
+        ));
+    }
+    let color = match name.as_str() {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" => Color::Gray,
+        "darkgray" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    };
+    Some(color)
+}
+
+const STATUS_MESSAGE_DURATION: Duration = Duration::from_secs(3);
+const WIPE_COUNTDOWN: Duration = Duration::from_secs(3);
+const QUOTE_DURATION: Duration = Duration::from_secs(15);
+/// Words ending with a period that do not end a sentence
+const ABBREVIATIONS: &[&str] = &[
+    "mr.", "mrs.", "ms.", "dr.", "prof.", "st.", "vs.", "etc.", "e.g.", "i.e.", "approx.", "no.",
+];
+const CELEBRATION_FRAME: Duration = Duration::from_millis(200);
+/// Deepest heading level inserted with Ctrl+G
+const MAX_HEADING_LEVEL: usize = 6;
+
+const DEFAULT_IDLE_QUOTES: &[&str] = &[
+    "There is nothing to writing. All you do is sit down at a typewriter and bleed. - Ernest Hemingway",
+    "You can't wait for inspiration. You have to go after it with a club. - Jack London",
+    "Start writing, no matter what. The water does not flow until the faucet is turned on. - Louis L'Amour",
+    "A professional writer is an amateur who didn't quit. - Richard Bach",
+];
 
 /// `writingbuddy` is a tool to support your writing without distractions
 #[derive(StructOpt, Debug)]
 #[structopt(name = "writingbuddy")]
 struct CliConfig {
     /// Path to config file. Can be a JSON, TOML, YAML, HJSON or INI file.
-    /// If nothing is specified a file named `writingbuddy` will be searched for.
-    /// If no config file is found default values will be used.
+    /// If nothing is specified a file named `writingbuddy` is searched for in the
+    /// current directory and then in the config directory, where a default config
+    /// is created if it is missing. If no config file can be loaded or created,
+    /// `--on-config-error` decides whether to exit or to use default values.
     #[structopt(short, long)]
     config_file: Option<String>,
 
@@ -46,26 +152,375 @@ struct CliConfig {
     /// created in the current directory if it does not exist.
     #[structopt(short, long)]
     initialize_config: bool,
+
+    /// Append the text to this file instead of the one given by `file_string`.
+    /// Missing parent directories are created.
+    #[structopt(long)]
+    append_to: Option<String>,
+
+    /// Start writing right away with the title given by `title_string`.
+    #[structopt(long)]
+    no_title: bool,
+
+    /// Print the file the text would be written to and exit.
+    #[structopt(long)]
+    print_filename: bool,
+
+    /// Start with the text piped into writingbuddy, e.g.
+    /// `cat draft.md | writingbuddy --edit-stdin`, and continue writing it.
+    #[structopt(long)]
+    edit_stdin: bool,
+
+    /// Continue the last entry of the most recently modified file named after
+    /// `file_string`. The entry is replaced when the session is saved.
+    #[structopt(long, conflicts_with_all = &["append-to", "edit-stdin"])]
+    continue_last: bool,
+
+    /// What to do if no config file can be loaded or created: "exit" with an
+    /// error, continue with the "defaults" or "prompt" whether to continue.
+    #[structopt(long, default_value = "exit", possible_values = &["exit", "defaults", "prompt"])]
+    on_config_error: String,
+}
+
+/// How to continue when loading the config fails, see `--on-config-error`
+enum ConfigErrorPolicy {
+    Exit,
+    Defaults,
+    Prompt,
+}
+
+impl ConfigErrorPolicy {
+    fn from_name(name: &str) -> ConfigErrorPolicy {
+        match name {
+            "defaults" => ConfigErrorPolicy::Defaults,
+            "prompt" => ConfigErrorPolicy::Prompt,
+            _ => ConfigErrorPolicy::Exit,
+        }
+    }
+}
+
+/// Everything that can make writingbuddy fail before or after a session
+#[derive(Debug)]
+enum WritingBuddyError {
+    /// A config file could not be read or parsed
+    Config {
+        path: String,
+        source: config::ConfigError,
+    },
+    /// A config file with the default settings could not be written
+    ConfigNotCreated(String),
+    /// There is no config directory to look for the config file in
+    NoConfigDirectory,
+    /// The path of the config file in the config directory is not valid UTF-8
+    ConfigPathNotUtf8(String),
+    /// --continue-last found no file named after `file_string`
+    NoDraftFound(String),
+    /// The file to continue has no entry starting with a title heading
+    NoEntryFound(String),
+    /// --edit-stdin was given but nothing is piped into writingbuddy
+    NoPipedInput,
+    /// The text piped into writingbuddy could not be read
+    PipedInput(io::Error),
+    /// The text has to be written to a file but the path is a directory
+    OutputIsDirectory(String),
+    /// The entry could not be written to the output file
+    Save { path: String, source: io::Error },
+    /// Setting up, drawing or restoring the terminal failed
+    Terminal(io::Error),
+}
+
+impl fmt::Display for WritingBuddyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WritingBuddyError::Config { path, source } => write!(
+                f,
+                "Failed loading config file {path:?}: {source}. Fix the file or pass another one with --config-file."
+            ),
+            WritingBuddyError::ConfigNotCreated(path) => write!(
+                f,
+                "Failed to create config file {path:?}. Check the permissions or pass --on-config-error defaults."
+            ),
+            WritingBuddyError::NoConfigDirectory => write!(
+                f,
+                "No config file found and no config directory available. Pass one with --config-file or --on-config-error defaults."
+            ),
+            WritingBuddyError::ConfigPathNotUtf8(path) => write!(
+                f,
+                "The config file path {path:?} is not valid UTF-8. Pass another one with --config-file or --on-config-error defaults."
+            ),
+            WritingBuddyError::NoDraftFound(directory) => write!(
+                f,
+                "No file named after `file_string` found in {directory:?} to continue."
+            ),
+            WritingBuddyError::NoEntryFound(path) => write!(
+                f,
+                "Found no entry starting with a title heading in {path:?} to continue."
+            ),
+            WritingBuddyError::NoPipedInput => write!(
+                f,
+                "--edit-stdin needs text piped into writingbuddy, e.g. `cat draft.md | writingbuddy --edit-stdin`."
+            ),
+            WritingBuddyError::PipedInput(err) => {
+                write!(f, "Failed to read the text piped into writingbuddy: {err}")
+            }
+            WritingBuddyError::OutputIsDirectory(path) => write!(
+                f,
+                "Cannot append text to {path:?} because it is a directory. Change `file_string` or use --append-to."
+            ),
+            WritingBuddyError::Save { path, source } => {
+                write!(f, "Failed to store text into {path:?}: {source}")
+            }
+            WritingBuddyError::Terminal(err) => write!(
+                f,
+                "Terminal error: {err}. Make sure writingbuddy runs in an interactive terminal."
+            ),
+        }
+    }
+}
+
+impl Error for WritingBuddyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WritingBuddyError::Config { source, .. } => Some(source),
+            WritingBuddyError::ConfigNotCreated(_)
+            | WritingBuddyError::NoConfigDirectory
+            | WritingBuddyError::ConfigPathNotUtf8(_)
+            | WritingBuddyError::NoDraftFound(_)
+            | WritingBuddyError::NoEntryFound(_)
+            | WritingBuddyError::NoPipedInput
+            | WritingBuddyError::OutputIsDirectory(_) => None,
+            WritingBuddyError::PipedInput(err) => Some(err),
+            WritingBuddyError::Save { source, .. } => Some(source),
+            WritingBuddyError::Terminal(err) => Some(err),
+        }
+    }
+}
+
+/// Messages from looking for and loading the config file. They are collected
+/// so that with `quiet_startup` nothing is printed right before the writing
+/// screen appears.
+#[derive(Default)]
+struct StartupMessages {
+    /// The messages in order and whether each one must always be shown
+    messages: Vec<(bool, String)>,
+}
+
+impl StartupMessages {
+    /// Progress while looking for the config, hidden with `quiet_startup`
+    fn info(&mut self, message: impl Into<String>) {
+        self.messages.push((false, message.into()));
+    }
+
+    /// Warnings and created files, always shown
+    fn notice(&mut self, message: impl Into<String>) {
+        self.messages.push((true, message.into()));
+    }
+
+    /// Prints the collected messages to stderr, the progress messages only
+    /// if `include_info` is set
+    fn print(&mut self, include_info: bool) {
+        for (important, message) in self.messages.drain(..) {
+            if important || include_info {
+                eprintln!("{message}");
+            }
+        }
+    }
+}
+
+/// How the words of the text are counted
+#[derive(Clone, Copy, PartialEq)]
+enum WordCountMode {
+    /// Everything separated by whitespace is a word
+    Plain,
+    /// Markdown syntax like heading and list markers is not counted
+    Markdown,
+    /// Markdown counting if the text looks like Markdown
+    Auto,
+}
+
+impl WordCountMode {
+    fn from_name(name: &str) -> WordCountMode {
+        match name {
+            "markdown" => WordCountMode::Markdown,
+            "auto" => WordCountMode::Auto,
+            _ => WordCountMode::Plain,
+        }
+    }
 }
 
 enum InputMode {
     Title,
     Writing,
+    Note,
 }
 
-/// App holds the state of the application
-struct App {
-    /// Current value of the title box
-    title: String,
-    /// Current value of the text box
-    text: String,
-    /// Current input mode
-    input_mode: InputMode,
+/// Format of the file the text is written to
+#[derive(Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Markdown,
+    Plain,
+    Org,
+}
+
+impl OutputFormat {
+    fn from_name(name: &str) -> OutputFormat {
+        match name {
+            "plain" => OutputFormat::Plain,
+            "org" => OutputFormat::Org,
+            _ => OutputFormat::Markdown,
+        }
+    }
+
+    fn default_title_string(self) -> &'static str {
+        match self {
+            OutputFormat::Markdown => "## %Y-%m-%d",
+            OutputFormat::Plain => "%Y-%m-%d",
+            OutputFormat::Org => "* %Y-%m-%d",
+        }
+    }
+
+    fn default_file_string(self) -> &'static str {
+        match self {
+            OutputFormat::Markdown => "%Y-%m.md",
+            OutputFormat::Plain => "%Y-%m.txt",
+            OutputFormat::Org => "%Y-%m.org",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Markdown => "md",
+            OutputFormat::Plain => "txt",
+            OutputFormat::Org => "org",
+        }
+    }
+
+    /// Character repeated to mark the level of a heading
+    fn heading_marker(self) -> Option<char> {
+        match self {
+            OutputFormat::Markdown => Some('#'),
+            OutputFormat::Org => Some('*'),
+            OutputFormat::Plain => None,
+        }
+    }
+
+    /// Level of the heading in the line, None if the line is no heading
+    fn heading_level(self, line: &str) -> Option<usize> {
+        let marker = self.heading_marker()?;
+        let level = line.chars().take_while(|&c| c == marker).count();
+        if level > 0 && line[level..].starts_with(' ') {
+            Some(level)
+        } else {
+            None
+        }
+    }
+
+    fn format_note(self, note: &str) -> String {
+        match self {
+            OutputFormat::Markdown => format!("<!-- {note} -->"),
+            OutputFormat::Plain => format!("Note: {note}"),
+            OutputFormat::Org => format!("# {note}"),
+        }
+    }
+
+    fn is_comment(self, line: &str) -> bool {
+        match self {
+            OutputFormat::Markdown => line.starts_with("<!--"),
+            OutputFormat::Plain => line.starts_with("Note: "),
+            OutputFormat::Org => line.starts_with("# "),
+        }
+    }
+}
+
+/// All keys read from the config file by `Settings::from_config`
+const CONFIG_KEYS: &[&str] = &[
+    "output_format",
+    "title_string",
+    "file_string",
+    "backspace_active",
+    "time_goal",
+    "word_goal",
+    "character_goal",
+    "strict_mode",
+    "keystroke_timeout",
+    "cap_time_display",
+    "min_terminal_width",
+    "min_terminal_height",
+    "timestamp_format",
+    "session_note",
+    "min_wpm",
+    "wpm_window",
+    "wpm_grace_period",
+    "wpm_delete_text",
+    "daily_target",
+    "passive_dim_level",
+    "time_warn_ratio",
+    "insert_under",
+    "goal_sounds",
+    "hide_stats_while_writing",
+    "line_min",
+    "line_max",
+    "save_title_only",
+    "auto_capitalize",
+    "record_goal_badge",
+    "goal_badge_format",
+    "timeout_grace_seconds",
+    "timeout_grace_after_paragraph",
+    "on_complete_cmd",
+    "idle_screensaver_seconds",
+    "idle_quotes",
+    "text_align",
+    "reset_time_on_timeout",
+    "timeout_confirm",
+    "show_paragraph_words",
+    "skip_title",
+    "hard_wrap_live",
+    "goal_schedule",
+    "time_format",
+    "double_newline_paragraphs",
+    "thousands_separator",
+    "themes",
+    "warmup_seconds",
+    "keystroke_timeout_start",
+    "keystroke_timeout_end",
+    "scroll_enabled",
+    "ratchet_step",
+    "ruler_column",
+    "project_dir",
+    "project_glob",
+    "project_goal",
+    "project_goal_counts",
+    "quiet_startup",
+    "show_recent_entries",
+    "entry_lead_blank",
+    "entry_trail_blank",
+    "continuous_goal",
+    "csv_log",
+    "timeout_resets_on_any_key",
+    "celebration_animation",
+    "celebration_seconds",
+    "show_sentence_stats",
+    "sentence_length_warning",
+    "word_count_mode",
+    "auto_archive",
+    "archive_dir",
+    "archive_dry_run",
+    "char_limit",
+    "char_limit_blocks_input",
+    "goal_typed_only",
+];
+
+/// Settings read from the config file
+struct Settings {
+    /// Format of the file the text is written to
+    output_format: OutputFormat,
+    /// Format string for the proposed title
+    title_string: String,
+    /// Format string for the file the text is appended to
+    file_string: String,
     /// Whether the backspace key works while writing
     backspace_active: bool,
 
-    writing_time: StopWatch,
-
     time_goal: Option<i64>,
 
     word_goal: Option<i64>,
@@ -74,118 +529,743 @@ struct App {
 
     strict_mode: bool,
 
-    last_keystroke: Option<Instant>,
-
     keystroke_timeout: Option<i64>,
+    /// Whether the displayed time stops counting once the time goal is reached
+    cap_time_display: bool,
+    /// Smallest terminal size in which the normal layout is rendered
+    min_terminal_size: (u16, u16),
+    /// Format string for timestamps inserted with Ctrl+T
+    timestamp_format: String,
+    /// Whether to ask for a note summarizing the session before saving
+    session_note: bool,
+    /// Minimal words per minute to keep up while writing
+    min_wpm: Option<i64>,
+    /// Length of the window in seconds over which the words per minute are measured
+    wpm_window: u64,
+    /// Seconds the words per minute may stay below `min_wpm`
+    wpm_grace_period: u64,
+    /// Whether the text is deleted when staying below `min_wpm` for too long
+    wpm_delete_text: bool,
+    /// Number of words to write per day, compared against the words in the output file
+    daily_target: Option<i64>,
+    /// How passive text is displayed: `normal`, `dim`, `dark` or `bright`
+    passive_dim_level: String,
+    /// Fraction of the time goal at the end during which the time is shown as a warning
+    time_warn_ratio: f64,
+    /// Heading in the output file under which the text is inserted
+    insert_under: Option<String>,
+    /// Whether to ring the terminal bell when a goal is reached
+    goal_sounds: bool,
+    /// Whether the stats are hidden while writing
+    hide_stats_while_writing: bool,
+    /// Lines shorter than this number of characters are highlighted
+    line_min: Option<i64>,
+    /// Lines longer than this number of characters are highlighted
+    line_max: Option<i64>,
+    /// Whether the title is saved even if no text was written
+    save_title_only: bool,
+    /// Whether the first letter of a sentence is capitalized automatically
+    auto_capitalize: bool,
+    /// Whether a line is added to the entry when the goals were reached
+    record_goal_badge: bool,
+    /// Format of the line added when the goals were reached
+    goal_badge_format: String,
+    /// Seconds after starting to write during which the keystroke timeout is paused
+    timeout_grace_seconds: u64,
+    /// Whether the grace period also starts after each paragraph break
+    timeout_grace_after_paragraph: bool,
+    /// Shell command run after the text was saved
+    on_complete_cmd: Option<String>,
+    /// Seconds without input on the title screen after which quotes are shown
+    idle_screensaver_seconds: Option<i64>,
+    /// Quotes shown when the title screen is idle
+    idle_quotes: Vec<String>,
+    /// Alignment of the text in the text box
+    text_align: Alignment,
+    /// Whether the writing time is reset when the text is deleted for not writing
+    reset_time_on_timeout: bool,
+    /// Whether a countdown is shown before the keystroke timeout deletes the text
+    timeout_confirm: bool,
+    /// Whether the number of words in the current paragraph is shown
+    show_paragraph_words: bool,
+    /// Whether writing starts right away without editing the title first
+    skip_title: bool,
+    /// Column after which a line break is inserted while typing
+    hard_wrap_live: Option<i64>,
+    /// Name of the entry of `goal_schedule` that set the goals
+    active_schedule: Option<String>,
+    /// Whether times are shown as HH:MM:SS instead of seconds
+    clock_time_format: bool,
+    /// Whether Enter starts a new paragraph with a blank line
+    double_newline_paragraphs: bool,
+    /// Separator inserted between groups of thousands in counts
+    thousands_separator: String,
+    /// Color themes which can be cycled through with F2, starting with the default theme
+    themes: Vec<Theme>,
+    /// Seconds of free writing before the session that are not saved
+    warmup_seconds: Option<i64>,
+    /// Keystroke timeout at the start of the session when scaling it over the time goal
+    keystroke_timeout_start: Option<i64>,
+    /// Keystroke timeout once the time goal is reached when scaling it
+    keystroke_timeout_end: Option<i64>,
+    /// Whether the text can always be scrolled, not only after reaching the goals
+    scroll_enabled: bool,
+    /// Every this many words the text is protected from the keystroke timeout
+    ratchet_step: Option<i64>,
+    /// Column after which a guide line is drawn in the text box
+    ruler_column: Option<i64>,
+    /// Directory with the files of a project whose words are shown next to the session
+    project_dir: Option<String>,
+    /// File names in the `project_dir` that are counted
+    project_glob: String,
+    /// Words the whole project should reach
+    project_goal: Option<i64>,
+    /// Whether the word goal is the `project_goal` instead of `word_goal`
+    project_goal_counts: bool,
+    /// Whether messages about loading the config are held back until the session ends
+    quiet_startup: bool,
+    /// Number of entries of the output file shown on the title screen
+    show_recent_entries: Option<i64>,
+    /// Blank lines between an appended entry and the text before it
+    entry_lead_blank: usize,
+    /// Blank lines written after each entry
+    entry_trail_blank: usize,
+    /// Whether the word goal has to be reached without a keystroke timeout
+    continuous_goal: bool,
+    /// CSV file a row with the stats of each session is appended to
+    csv_log: Option<String>,
+    /// Whether every key resets the keystroke timeout, not only typed characters
+    timeout_resets_on_any_key: bool,
+    /// Whether an animation is shown when the word goal is reached
+    celebration_animation: bool,
+    /// How long the celebration animation is shown
+    celebration_seconds: u64,
+    /// Whether the number of sentences and their average length are shown
+    show_sentence_stats: bool,
+    /// Average words per sentence above which the sentence stats are highlighted
+    sentence_length_warning: Option<i64>,
+    /// How the words of the text are counted
+    word_count_mode: WordCountMode,
+    /// Whether files of past periods are moved into the `archive_dir` at startup
+    auto_archive: bool,
+    /// Directory next to the output files that old files are moved to
+    archive_dir: String,
+    /// Whether the files to archive are only listed instead of moved
+    archive_dry_run: bool,
+    /// Most characters the text should have, for example for a social media post
+    char_limit: Option<i64>,
+    /// Whether typing stops at the `char_limit`
+    char_limit_blocks_input: bool,
+    /// Whether only the words typed in the session count towards the word goal
+    goal_typed_only: bool,
+}
+
+impl Settings {
+    fn from_config(config: &Config) -> Settings {
+        let output_format =
+            OutputFormat::from_name(&config.get_string("output_format").unwrap_or_default());
+        let scheduled_goals = get_scheduled_goals(config, Local::now().hour() as i64);
+        let mut settings = Settings {
+            output_format,
+            title_string: config
+                .get_string("title_string")
+                .unwrap_or_else(|_| output_format.default_title_string().to_string()),
+            file_string: config
+                .get_string("file_string")
+                .unwrap_or_else(|_| output_format.default_file_string().to_string()),
+            backspace_active: config.get_bool("backspace_active").unwrap_or(true),
+            time_goal: get_optional_int(config, "time_goal"),
+            word_goal: get_optional_int(config, "word_goal"),
+            character_goal: get_optional_int(config, "character_goal"),
+            strict_mode: config.get_bool("strict_mode").unwrap_or(true),
+            keystroke_timeout: get_optional_int(config, "keystroke_timeout"),
+            cap_time_display: config.get_bool("cap_time_display").unwrap_or(false),
+            min_terminal_size: (
+                config.get_int("min_terminal_width").unwrap_or(40) as u16,
+                config.get_int("min_terminal_height").unwrap_or(16) as u16,
+            ),
+            timestamp_format: config
+                .get_string("timestamp_format")
+                .unwrap_or_else(|_| "%H:%M".to_string()),
+            session_note: config.get_bool("session_note").unwrap_or(false),
+            min_wpm: get_optional_int(config, "min_wpm"),
+            wpm_window: config.get_int("wpm_window").unwrap_or(60).max(1) as u64,
+            wpm_grace_period: config.get_int("wpm_grace_period").unwrap_or(10) as u64,
+            wpm_delete_text: config.get_bool("wpm_delete_text").unwrap_or(false),
+            daily_target: get_optional_int(config, "daily_target"),
+            passive_dim_level: config
+                .get_string("passive_dim_level")
+                .unwrap_or_else(|_| "normal".to_string()),
+            time_warn_ratio: config.get_float("time_warn_ratio").unwrap_or(0.1),
+            insert_under: config
+                .get_string("insert_under")
+                .ok()
+                .filter(|heading| !heading.is_empty()),
+            goal_sounds: config.get_bool("goal_sounds").unwrap_or(false),
+            hide_stats_while_writing: config.get_bool("hide_stats_while_writing").unwrap_or(false),
+            line_min: get_optional_int(config, "line_min"),
+            line_max: get_optional_int(config, "line_max"),
+            save_title_only: config.get_bool("save_title_only").unwrap_or(false),
+            auto_capitalize: config.get_bool("auto_capitalize").unwrap_or(false),
+            record_goal_badge: config.get_bool("record_goal_badge").unwrap_or(false),
+            goal_badge_format: config
+                .get_string("goal_badge_format")
+                .unwrap_or_else(|_| "(goal: {words} words ✓ in {minutes} min)".to_string()),
+            timeout_grace_seconds: config.get_int("timeout_grace_seconds").unwrap_or(0) as u64,
+            timeout_grace_after_paragraph: config
+                .get_bool("timeout_grace_after_paragraph")
+                .unwrap_or(false),
+            on_complete_cmd: config
+                .get_string("on_complete_cmd")
+                .ok()
+                .filter(|command| !command.is_empty()),
+            idle_screensaver_seconds: get_optional_int(config, "idle_screensaver_seconds"),
+            idle_quotes: config
+                .get_array("idle_quotes")
+                .map(|quotes| {
+                    quotes
+                        .into_iter()
+                        .filter_map(|quote| quote.into_string().ok())
+                        .collect()
+                })
+                .unwrap_or_else(|_| DEFAULT_IDLE_QUOTES.iter().map(|q| q.to_string()).collect()),
+            text_align: match config.get_string("text_align").as_deref() {
+                Ok("center") => Alignment::Center,
+                Ok("right") => Alignment::Right,
+                _ => Alignment::Left,
+            },
+            reset_time_on_timeout: config.get_bool("reset_time_on_timeout").unwrap_or(true),
+            timeout_confirm: config.get_bool("timeout_confirm").unwrap_or(false),
+            show_paragraph_words: config.get_bool("show_paragraph_words").unwrap_or(false),
+            skip_title: config.get_bool("skip_title").unwrap_or(false),
+            hard_wrap_live: get_optional_int(config, "hard_wrap_live"),
+            active_schedule: None,
+            clock_time_format: config
+                .get_string("time_format")
+                .is_ok_and(|format| format == "clock"),
+            double_newline_paragraphs: config
+                .get_bool("double_newline_paragraphs")
+                .unwrap_or(false),
+            thousands_separator: config.get_string("thousands_separator").unwrap_or_default(),
+            themes: std::iter::once(Theme::default())
+                .chain(
+                    config
+                        .get_array("themes")
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter_map(|theme| theme.into_table().ok())
+                        .map(|theme| Theme::from_table(&theme)),
+                )
+                .collect(),
+            warmup_seconds: get_optional_int(config, "warmup_seconds"),
+            keystroke_timeout_start: get_optional_int(config, "keystroke_timeout_start"),
+            keystroke_timeout_end: get_optional_int(config, "keystroke_timeout_end"),
+            scroll_enabled: config.get_bool("scroll_enabled").unwrap_or(false),
+            ratchet_step: get_optional_int(config, "ratchet_step"),
+            ruler_column: get_optional_int(config, "ruler_column"),
+            project_dir: config
+                .get_string("project_dir")
+                .ok()
+                .filter(|dir| !dir.is_empty()),
+            project_glob: config
+                .get_string("project_glob")
+                .unwrap_or_else(|_| "*".to_string()),
+            project_goal: get_optional_int(config, "project_goal"),
+            project_goal_counts: config.get_bool("project_goal_counts").unwrap_or(false),
+            quiet_startup: config.get_bool("quiet_startup").unwrap_or(false),
+            show_recent_entries: get_optional_int(config, "show_recent_entries"),
+            entry_lead_blank: config.get_int("entry_lead_blank").unwrap_or(0).max(0) as usize,
+            entry_trail_blank: config.get_int("entry_trail_blank").unwrap_or(1).max(0) as usize,
+            continuous_goal: config.get_bool("continuous_goal").unwrap_or(false),
+            csv_log: config
+                .get_string("csv_log")
+                .ok()
+                .filter(|path| !path.is_empty()),
+            timeout_resets_on_any_key: config
+                .get_bool("timeout_resets_on_any_key")
+                .unwrap_or(false),
+            celebration_animation: config.get_bool("celebration_animation").unwrap_or(false),
+            celebration_seconds: config.get_int("celebration_seconds").unwrap_or(3).max(0) as u64,
+            show_sentence_stats: config.get_bool("show_sentence_stats").unwrap_or(false),
+            sentence_length_warning: get_optional_int(config, "sentence_length_warning"),
+            word_count_mode: WordCountMode::from_name(
+                &config
+                    .get_string("word_count_mode")
+                    .unwrap_or_else(|_| "plain".to_string()),
+            ),
+            auto_archive: config.get_bool("auto_archive").unwrap_or(false),
+            archive_dir: config
+                .get_string("archive_dir")
+                .unwrap_or_else(|_| "archive".to_string()),
+            archive_dry_run: config.get_bool("archive_dry_run").unwrap_or(false),
+            char_limit: get_optional_int(config, "char_limit"),
+            char_limit_blocks_input: config.get_bool("char_limit_blocks_input").unwrap_or(false),
+            goal_typed_only: config.get_bool("goal_typed_only").unwrap_or(true),
+        };
+        if let Some((name, word_goal, time_goal)) = scheduled_goals {
+            settings.word_goal = word_goal.or(settings.word_goal);
+            settings.time_goal = time_goal.or(settings.time_goal);
+            settings.active_schedule = Some(name);
+        }
+        settings
+    }
+}
+
+/// Finds the entry of `goal_schedule` whose hours contain `hour`.
+/// Returns a description of the entry with its word and time goal.
+fn get_scheduled_goals(config: &Config, hour: i64) -> Option<(String, Option<i64>, Option<i64>)> {
+    let schedule = config.get_array("goal_schedule").ok()?;
+    schedule.into_iter().find_map(|entry| {
+        let entry = entry.into_table().ok()?;
+        let get_int = |key: &str| entry.get(key).and_then(|v| v.clone().into_int().ok());
+        let (from, to) = (get_int("from")?, get_int("to")?);
+        let active = if from <= to {
+            from <= hour && hour < to
+        } else {
+            // The range goes past midnight
+            hour >= from || hour < to
+        };
+        if !active {
+            return None;
+        }
+        let name = entry
+            .get("name")
+            .and_then(|v| v.clone().into_string().ok())
+            .unwrap_or_else(|| format!("{from}-{to} h"));
+        let word_goal = get_int("word_goal").filter(|&goal| goal != 0);
+        let time_goal = get_int("time_goal").filter(|&goal| goal != 0);
+        Some((name, word_goal, time_goal))
+    })
+}
+
+/// Reads an integer setting where a value of 0 means the setting is disabled
+fn get_optional_int(config: &Config, key: &str) -> Option<i64> {
+    config.get_int(key).ok().filter(|&value| value != 0)
+}
+
+/// App holds the state of the application
+struct App {
+    /// Current value of the title box
+    title: String,
+    /// Current value of the text box
+    text: String,
+    /// File the text is appended to
+    output_file: String,
+    /// Note summarizing the session
+    note: String,
+    /// Current input mode
+    input_mode: InputMode,
+    /// Whether the title is edited during a running writing session
+    editing_title: bool,
+
+    settings: Settings,
+
+    writing_time: StopWatch,
+
+    last_keystroke: Option<Instant>,
+    /// Since when the countdown before deleting the text is running
+    wipe_pending_since: Option<Instant>,
+    /// Time of the last key event, used for the idle screensaver
+    last_event: Instant,
+    /// Start of the last grace period of the keystroke timeout
+    grace_start: Option<Instant>,
+    /// Word count after each keystroke within the last `wpm_window` seconds
+    word_samples: VecDeque<(Instant, usize)>,
+    /// Since when the words per minute are below `min_wpm`
+    wpm_below_since: Option<Instant>,
+    /// Start of the words per minute measurement, restarted when the text
+    /// is deleted so that a new window starts empty
+    wpm_window_start: Instant,
+    /// Words already stored in the output file before this session
+    written_words: usize,
+    /// Words in the files of the `project_dir` before this session
+    project_words: Option<usize>,
+    /// Whether the session is still in the warm-up phase
+    warming_up: bool,
+    /// Index of the current theme in `themes`
+    theme_index: usize,
+    /// Message shown in place of the instructions for a short time
+    status_message: Option<(Instant, String)>,
+    /// Whether reaching the word goal was already signaled
+    word_goal_notified: bool,
+    /// Whether reaching the time goal was already signaled
+    time_goal_notified: bool,
+    /// Number of wrapped lines the text view is scrolled up from the end
+    scroll_offset: usize,
+    /// Rows and columns of the text view at the last render
+    paragraph_size: Cell<(usize, usize)>,
+    /// Length of the text a keystroke timeout cannot delete, see `ratchet_step`
+    ratchet_floor: usize,
+    /// Last entries of the output file shown on the title screen
+    recent_entries: String,
+    /// Words left after the last keystroke timeout, see `continuous_goal`
+    clean_run_start: Option<usize>,
+    /// Since when the celebration for reaching the word goal is shown
+    celebration_start: Option<Instant>,
+    /// Whether reaching the word goal was already celebrated
+    word_goal_celebrated: bool,
+    /// When turning off strict mode was requested, it needs a confirmation
+    strict_off_requested: Option<Instant>,
+    /// Text piped in with --edit-stdin or continued with --continue-last
+    existing_text: String,
+    /// Words of the `existing_text`
+    existing_words: usize,
+    /// Offset in the output file of the entry continued with --continue-last.
+    /// The entry is replaced when saving.
+    continued_entry_start: Option<usize>,
 }
 
 impl App {
-    fn new(
-        title: String,
-        time_goal: Option<i64>,
-        word_goal: Option<i64>,
-        character_goal: Option<i64>,
-        backspace_active: bool,
-        strict_mode: bool,
-        keystroke_timeout: Option<i64>,
-    ) -> App {
-        App {
+    fn new(title: String, output_file: String, settings: Settings) -> App {
+        let mut app = App {
             title,
             text: String::default(),
+            output_file,
+            note: String::default(),
             input_mode: InputMode::Title,
-            backspace_active,
-            time_goal,
-            word_goal,
-            character_goal,
+            editing_title: false,
+            settings,
             writing_time: StopWatch::new(),
-            strict_mode,
             last_keystroke: None,
-            keystroke_timeout,
+            wipe_pending_since: None,
+            last_event: Instant::now(),
+            grace_start: None,
+            word_samples: VecDeque::new(),
+            wpm_below_since: None,
+            wpm_window_start: Instant::now(),
+            written_words: 0,
+            project_words: None,
+            warming_up: false,
+            theme_index: 0,
+            status_message: None,
+            word_goal_notified: false,
+            time_goal_notified: false,
+            scroll_offset: 0,
+            paragraph_size: Cell::new((0, 0)),
+            ratchet_floor: 0,
+            recent_entries: String::default(),
+            clean_run_start: None,
+            celebration_start: None,
+            word_goal_celebrated: false,
+            strict_off_requested: None,
+            existing_text: String::default(),
+            existing_words: 0,
+            continued_entry_start: None,
+        };
+        app.warming_up = app.settings.warmup_seconds.is_some();
+        if app.settings.skip_title {
+            app.start_writing();
+        }
+        app
+    }
+
+    /// Switches from the title to writing and starts the writing time
+    fn start_writing(&mut self) {
+        self.scroll_offset = 0;
+        self.wpm_window_start = Instant::now();
+        self.writing_time.start();
+        self.grace_start = Some(Instant::now());
+        self.input_mode = InputMode::Writing;
+    }
+
+    /// Formats a count with the configured `thousands_separator`
+    fn format_count(&self, count: i64) -> String {
+        let separator = &self.settings.thousands_separator;
+        let digits = count.unsigned_abs().to_string();
+        let mut formatted = String::new();
+        for (index, digit) in digits.chars().enumerate() {
+            if index > 0 && (digits.len() - index).is_multiple_of(3) {
+                formatted.push_str(separator);
+            }
+            formatted.push(digit);
+        }
+        if count < 0 {
+            formatted.insert(0, '-');
         }
+        formatted
+    }
+
+    /// Words of the session, counted according to the `word_count_mode`
+    fn get_word_count(&self) -> usize {
+        count_words(&self.text, self.settings.word_count_mode)
     }
 
     fn get_word_count_string(&self) -> String {
-        let word_count = self.text.split_whitespace().count();
-        match self.word_goal {
-            Some(word_goal) => format!("{word_count}/{word_goal}"),
-            None => format!("{word_count}"),
+        let word_count = self.format_count(self.get_word_count() as i64);
+        let session = match self.settings.word_goal {
+            Some(word_goal) => format!("{word_count}/{}", self.format_count(word_goal)),
+            None => word_count,
+        };
+        match self.get_project_word_count() {
+            Some(project_words) => {
+                let project_words = self.format_count(project_words as i64);
+                match self.settings.project_goal {
+                    Some(project_goal) => format!(
+                        "{session} (project {project_words}/{})",
+                        self.format_count(project_goal)
+                    ),
+                    None => format!("{session} (project {project_words})"),
+                }
+            }
+            None => session,
         }
     }
 
+    /// Words of the whole project including this session, if a `project_dir` is set
+    fn get_project_word_count(&self) -> Option<usize> {
+        self.project_words
+            .map(|words| words + self.get_word_count())
+    }
+
     fn get_character_count_string(&self) -> String {
-        let character_count = self.text.chars().count();
-        match self.character_goal {
-            Some(character_goal) => format!("{character_count}/{character_goal}"),
-            None => format!("{character_count}"),
+        let character_count = self.format_count(self.text.chars().count() as i64);
+        match self.settings.character_goal {
+            Some(character_goal) => {
+                format!("{character_count}/{}", self.format_count(character_goal))
+            }
+            None => character_count,
         }
     }
 
-    fn get_time_string(&self) -> String {
+    /// Seconds to show in the time widget. Stops at the time goal if
+    /// `cap_time_display` is set, the stopwatch itself keeps running.
+    fn get_displayed_time(&self) -> u64 {
         let duration = self.writing_time.elapsed().as_secs();
-        match self.time_goal {
-            Some(time_goal) => format!("{duration} s/{time_goal} s"),
-            None => format!("{duration} s"),
+        match self.settings.time_goal {
+            Some(time_goal) if self.settings.cap_time_display => duration.min(time_goal as u64),
+            _ => duration,
+        }
+    }
+
+    /// Formats seconds according to the `time_format` setting
+    fn format_seconds(&self, seconds: u64) -> String {
+        if !self.settings.clock_time_format {
+            return format!("{seconds} s");
+        }
+        let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+        if hours > 0 {
+            format!("{hours:02}:{minutes:02}:{seconds:02}")
+        } else {
+            format!("{minutes:02}:{seconds:02}")
+        }
+    }
+
+    fn get_time_string(&self) -> String {
+        let duration = self.get_displayed_time();
+        let elapsed = self.format_seconds(duration);
+        match self.settings.time_goal {
+            Some(time_goal) if self.settings.cap_time_display && duration >= time_goal as u64 => {
+                format!("{elapsed}/{} ✓", self.format_seconds(time_goal as u64))
+            }
+            Some(time_goal) => format!("{elapsed}/{}", self.format_seconds(time_goal as u64)),
+            None => elapsed,
         }
     }
 
     fn get_time_color(&self) -> Color {
-        let duration = self.writing_time.elapsed().as_secs();
-        match self.time_goal {
+        let theme = self.get_theme();
+        let duration = self.get_displayed_time();
+        match self.settings.time_goal {
             Some(i) => {
                 if duration as i64 >= i {
-                    DONE_COLOR
+                    theme.done
+                } else if duration as f64 >= (1.0 - self.settings.time_warn_ratio) * i as f64 {
+                    theme.warning
                 } else {
-                    ACTIVE_COLOR
+                    theme.active
                 }
             }
-            None => PASSIVE_COLOR,
+            None => theme.passive,
         }
     }
 
     fn get_word_count_color(&self) -> Color {
-        let word_count = self.text.split_whitespace().count();
-        match self.word_goal {
-            Some(i) => {
-                if word_count as i64 >= i {
-                    DONE_COLOR
-                } else {
-                    WARNING_COLOR
-                }
-            }
-            None => PASSIVE_COLOR,
+        let theme = self.get_theme();
+        match self.get_remaining_words() {
+            Some(remaining) if remaining > 0 => theme.warning,
+            Some(_) => theme.done,
+            None => theme.passive,
         }
     }
 
     fn get_character_count_color(&self) -> Color {
+        let theme = self.get_theme();
         let character_count = self.text.chars().count();
-        match self.character_goal {
+        match self.settings.character_goal {
             Some(i) => {
                 if character_count as i64 >= i {
-                    DONE_COLOR
+                    theme.done
                 } else {
-                    WARNING_COLOR
+                    theme.warning
                 }
             }
-            None => PASSIVE_COLOR,
+            None => theme.passive,
         }
     }
 
-    fn achieved_goals(&self) -> bool {
-        let word_goal_achieved = match self.word_goal {
-            Some(i) => self.text.split_whitespace().count() as i64 >= i,
-            None => true,
-        };
-        let time_goal_achieved = match self.time_goal {
+    /// Characters left until the `char_limit`, negative once it is exceeded
+    fn get_char_remaining(&self) -> Option<i64> {
+        self.settings
+            .char_limit
+            .map(|limit| limit - self.text.chars().count() as i64)
+    }
+
+    fn get_char_remaining_string(&self) -> String {
+        match self.get_char_remaining() {
+            Some(remaining) if remaining < 0 => {
+                format!("{} over", self.format_count(-remaining))
+            }
+            Some(remaining) => format!("{} left", self.format_count(remaining)),
+            None => String::new(),
+        }
+    }
+
+    fn get_char_remaining_color(&self) -> Color {
+        let theme = self.get_theme();
+        match self.get_char_remaining() {
+            Some(remaining) if remaining < 0 => theme.danger,
+            _ => theme.passive,
+        }
+    }
+
+    /// Whether typing is blocked because the `char_limit` is reached
+    fn reached_char_limit(&self) -> bool {
+        self.settings.char_limit_blocks_input
+            && self
+                .get_char_remaining()
+                .is_some_and(|remaining| remaining <= 0)
+    }
+
+    /// Applies an edit of the text, unless it makes the text longer than a
+    /// `char_limit` that blocks input
+    fn edit_within_char_limit(&mut self, edit: impl FnOnce(&mut App)) {
+        let previous_chars = self.text.chars().count();
+        let previous = (self.text.clone(), self.ratchet_floor);
+        edit(self);
+        let over_limit = self.settings.char_limit_blocks_input
+            && self
+                .get_char_remaining()
+                .is_some_and(|remaining| remaining < 0);
+        if over_limit && self.text.chars().count() > previous_chars {
+            (self.text, self.ratchet_floor) = previous;
+            self.set_status_message("This does not fit into the character limit".to_string());
+        }
+    }
+
+    fn get_schedule_string(&self) -> String {
+        match &self.settings.active_schedule {
+            Some(name) => format!(" Goals for {name}."),
+            None => String::default(),
+        }
+    }
+
+    /// Words written this month compared to `daily_target` for each day so far
+    fn get_target_progress_string(&self) -> String {
+        match self.settings.daily_target {
+            Some(daily_target) => {
+                let expected = daily_target * Utc::now().day() as i64;
+                let written = (self.written_words + self.get_word_count()) as i64;
+                if written >= expected {
+                    format!(" Ahead by {} words.", self.format_count(written - expected))
+                } else {
+                    format!(
+                        " Behind by {} words.",
+                        self.format_count(expected - written)
+                    )
+                }
+            }
+            None => String::default(),
+        }
+    }
+
+    fn achieved_word_goal(&self) -> bool {
+        !matches!(self.get_remaining_words(), Some(remaining) if remaining > 0)
+    }
+
+    /// Words left until the word goal, or until the `project_goal` if that
+    /// one counts. None without a word goal.
+    fn get_remaining_words(&self) -> Option<i64> {
+        if self.settings.project_goal_counts {
+            if let (Some(project_words), Some(project_goal)) =
+                (self.get_project_word_count(), self.settings.project_goal)
+            {
+                return Some(project_goal - project_words as i64);
+            }
+        }
+        self.settings
+            .word_goal
+            .map(|word_goal| word_goal - self.get_goal_word_count() as i64)
+    }
+
+    /// Words counting towards the word goal. With `continuous_goal` only the
+    /// words written since the last keystroke timeout count.
+    fn get_goal_word_count(&self) -> usize {
+        let word_count = self.get_word_count();
+        match (self.settings.continuous_goal, self.clean_run_start) {
+            (true, Some(start)) => word_count.saturating_sub(start),
+            _ if self.settings.goal_typed_only => word_count.saturating_sub(self.existing_words),
+            _ => word_count,
+        }
+    }
+
+    /// Starts with text piped in with --edit-stdin or continued with
+    /// --continue-last. During a warm-up the text is only shown once the
+    /// warm-up ends.
+    fn load_existing_text(&mut self, text: &str) {
+        self.existing_text = text.replace("\r\n", "\n").trim_end().to_string();
+        if !self.warming_up {
+            self.apply_existing_text();
+        }
+    }
+
+    /// Puts the existing text into the text box, the keystroke timeout never deletes it
+    fn apply_existing_text(&mut self) {
+        self.text = self.existing_text.clone();
+        self.existing_words = self.get_word_count();
+        self.ratchet_floor = self.text.len();
+    }
+
+    fn achieved_time_goal(&self) -> bool {
+        match self.settings.time_goal {
             Some(i) => self.writing_time.elapsed().as_secs() as i64 >= i,
             None => true,
-        };
-        word_goal_achieved && time_goal_achieved
+        }
+    }
+
+    /// Describes what is missing to reach the goals, e.g. "120 words and 40 s"
+    fn get_remaining_goals_string(&self) -> String {
+        let mut remaining = Vec::new();
+        if let Some(remaining_words) = self.get_remaining_words().filter(|&words| words > 0) {
+            let remaining_words = self.format_count(remaining_words);
+            remaining.push(format!("{remaining_words} words"));
+        }
+        if let (Some(time_goal), false) = (self.settings.time_goal, self.achieved_time_goal()) {
+            let duration = self.writing_time.elapsed().as_secs() as i64;
+            remaining.push(self.format_seconds((time_goal - duration) as u64));
+        }
+        remaining.join(" and ")
+    }
+
+    fn achieved_goals(&self) -> bool {
+        self.achieved_word_goal() && self.achieved_time_goal()
     }
 
-    fn get_instruction(&self) -> Vec<Span> {
+    fn get_instruction(&self) -> Vec<Span<'_>> {
+        if let Some((since, message)) = &self.status_message {
+            if since.elapsed() < STATUS_MESSAGE_DURATION {
+                return vec![Span::raw(message.clone())];
+            }
+        }
         match self.input_mode {
+            InputMode::Title if self.editing_title => vec![
+                Span::styled(
+                    "Press [Enter]",
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(" to continue writing."),
+            ],
             InputMode::Title => {
-                let save_and = if self.has_text() { " save and" } else { "" };
+                let save_and = if self.has_entry() { " save and" } else { "" };
                 vec![
                     Span::raw("Press "),
                     Span::styled("[Esc]", Style::default().add_modifier(Modifier::BOLD)),
@@ -195,277 +1275,1522 @@ impl App {
                         Style::default().add_modifier(Modifier::BOLD),
                     ),
                     Span::raw(" to start the writing session."),
+                    Span::raw(self.get_target_progress_string()),
+                    Span::raw(self.get_schedule_string()),
+                ]
+            }
+            InputMode::Writing if self.warming_up => {
+                let warmup = self.settings.warmup_seconds.unwrap_or(0) as u64;
+                let remaining = warmup.saturating_sub(self.writing_time.elapsed().as_secs());
+                vec![
+                    Span::raw(format!(
+                        "Warm-up: {} left. This text will not be saved. Press ",
+                        self.format_seconds(remaining)
+                    )),
+                    Span::styled("[Esc]", Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(" to stop."),
                 ]
             }
             InputMode::Writing => {
-                if self.strict_mode && !self.achieved_goals() {
-                    vec![Span::raw(
-                        "Keep writing until you achieve your writing goal! ",
-                    )]
+                if self.settings.strict_mode && !self.achieved_goals() {
+                    let clean_run =
+                        if self.settings.continuous_goal && self.clean_run_start.is_some() {
+                            "Clean run broken, the word goal starts over. "
+                        } else {
+                            ""
+                        };
+                    vec![Span::raw(format!(
+                        "{clean_run}Keep writing until you achieve your writing goal! {} to go.",
+                        self.get_remaining_goals_string()
+                    ))]
                 } else {
                     vec![
                         Span::raw("Press "),
                         Span::styled("[Esc]", Style::default().add_modifier(Modifier::BOLD)),
-                        Span::raw(" to stop writing"),
+                        Span::raw(" to stop writing. Press "),
+                        Span::styled("[Tab]", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to edit the title."),
                     ]
                 }
             }
+            InputMode::Note => vec![
+                Span::raw("Summarize the session in one line. "),
+                Span::styled(
+                    "Press [Enter]",
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(" to save and exit."),
+            ],
+        }
+    }
+
+    /// Records the current word count for the words per minute measurement
+    fn record_word_count(&mut self) {
+        let window = Duration::from_secs(self.settings.wpm_window);
+        // The last sample before the window is kept as the baseline of the window
+        while let Some((instant, _)) = self.word_samples.get(1) {
+            if instant.elapsed() > window {
+                self.word_samples.pop_front();
+            } else {
+                break;
+            }
+        }
+        let word_count = self.get_word_count();
+        self.word_samples.push_back((Instant::now(), word_count));
+        self.update_ratchet_floor();
+    }
+
+    /// Moves the ratchet floor behind the last completed word once another
+    /// `ratchet_step` words are written
+    fn update_ratchet_floor(&mut self) {
+        let Some(step) = self.settings.ratchet_step else {
+            return;
+        };
+        // The word currently being typed is not banked yet
+        let completed = self.text.trim_end_matches(|c: char| !c.is_whitespace());
+        let completed_words = completed.split_whitespace().count() as i64;
+        let banked_words = self.text[..self.ratchet_floor].split_whitespace().count() as i64;
+        if completed_words / step > banked_words / step {
+            self.ratchet_floor = completed.len();
         }
     }
 
+    /// Words per minute within the last `wpm_window` seconds. None until a
+    /// full window passed since writing started or the text was deleted.
+    fn get_rolling_wpm(&self) -> Option<f32> {
+        let window = self.settings.wpm_window;
+        let window_duration = Duration::from_secs(window);
+        if self.wpm_window_start.elapsed() < window_duration {
+            return None;
+        }
+        let word_count = self.get_word_count();
+        // Words at the start of the window. Without any samples nothing was
+        // typed within the window.
+        let words_before = self
+            .word_samples
+            .iter()
+            .rev()
+            .find(|(instant, _)| instant.elapsed() > window_duration)
+            .or_else(|| self.word_samples.front())
+            .map_or(word_count, |(_, word_count)| *word_count);
+        let words_in_window = word_count.saturating_sub(words_before);
+        Some(words_in_window as f32 * 60.0 / window as f32)
+    }
+
+    fn get_wpm_color(&self) -> Option<Color> {
+        let theme = self.get_theme();
+        let min_wpm = self.settings.min_wpm? as f32;
+        let wpm = self.get_rolling_wpm()?;
+        if wpm < min_wpm {
+            Some(theme.danger)
+        } else if wpm < 1.2 * min_wpm {
+            Some(theme.warning)
+        } else {
+            None
+        }
+    }
+
+    /// Warning with the seconds left before the keystroke timeout deletes the
+    /// text, shown once the text box changes its color
+    fn get_timeout_warning(&self) -> Option<String> {
+        let writing = matches!(self.input_mode, InputMode::Writing) || self.editing_title;
+        if !writing || self.warming_up || self.wipe_pending_since.is_some() {
+            return None;
+        }
+        let timeout = self.get_keystroke_timeout()?;
+        let idle_time = self.get_idle_time()?.as_secs_f32();
+        if idle_time <= 0.5 * timeout {
+            return None;
+        }
+        let remaining = (timeout - idle_time).max(0.0).ceil();
+        Some(format!("Keep writing! Text is deleted in {remaining}s"))
+    }
+
     fn get_widget_colors(&self) -> (Color, Color) {
+        let theme = self.get_theme();
         match self.input_mode {
-            InputMode::Title => (ACTIVE_COLOR, PASSIVE_COLOR),
-            InputMode::Writing => match (self.keystroke_timeout, self.last_keystroke) {
-                (Some(timeout), Some(last_keystroke)) => {
-                    if last_keystroke.elapsed().as_secs_f32() > 0.8 * timeout as f32 {
-                        (PASSIVE_COLOR, DANGER_COLOR)
-                    } else if last_keystroke.elapsed().as_secs_f32() > 0.5 * timeout as f32 {
-                        (PASSIVE_COLOR, WARNING_COLOR)
+            InputMode::Title => (theme.active, theme.passive),
+            InputMode::Writing if self.warming_up => (theme.passive, theme.done),
+            InputMode::Writing => match (self.get_keystroke_timeout(), self.get_idle_time()) {
+                (Some(timeout), Some(idle_time)) => {
+                    if idle_time.as_secs_f32() > 0.8 * timeout {
+                        (theme.passive, theme.danger)
+                    } else if idle_time.as_secs_f32() > 0.5 * timeout {
+                        (theme.passive, theme.warning)
                     } else {
-                        (PASSIVE_COLOR, ACTIVE_COLOR)
+                        (theme.passive, self.get_wpm_color().unwrap_or(theme.active))
                     }
                 }
-                _ => (PASSIVE_COLOR, ACTIVE_COLOR),
+                _ => (theme.passive, self.get_wpm_color().unwrap_or(theme.active)),
             },
+            InputMode::Note => (theme.passive, theme.passive),
+        }
+    }
+
+    /// Current keystroke timeout in seconds. If `keystroke_timeout_start` and
+    /// `keystroke_timeout_end` are set, it moves linearly from the start to the
+    /// end value while working towards the time goal.
+    fn get_keystroke_timeout(&self) -> Option<f32> {
+        let scaling = (
+            self.settings.keystroke_timeout_start,
+            self.settings.keystroke_timeout_end,
+            self.settings.time_goal,
+        );
+        match scaling {
+            (Some(start), Some(end), Some(time_goal)) => {
+                let elapsed = self.writing_time.elapsed().as_secs_f32();
+                let progress = (elapsed / time_goal as f32).min(1.0);
+                Some(start as f32 + (end - start) as f32 * progress)
+            }
+            _ => self
+                .settings
+                .keystroke_timeout
+                .map(|timeout| timeout as f32),
+        }
+    }
+
+    /// Time since the last keystroke, not counting the grace period of the keystroke timeout
+    fn get_idle_time(&self) -> Option<Duration> {
+        let idle_time = self.last_keystroke?.elapsed();
+        match self.grace_start {
+            Some(grace_start) => {
+                let grace_end =
+                    grace_start + Duration::from_secs(self.settings.timeout_grace_seconds);
+                Some(idle_time.min(Instant::now().saturating_duration_since(grace_end)))
+            }
+            None => Some(idle_time),
+        }
+    }
+
+    /// Whether the title screen is idle long enough to show the screensaver
+    fn is_idle(&self) -> bool {
+        let title_screen = matches!(self.input_mode, InputMode::Title) && !self.editing_title;
+        match self.settings.idle_screensaver_seconds {
+            Some(seconds) => title_screen && self.last_event.elapsed().as_secs() >= seconds as u64,
+            None => false,
+        }
+    }
+
+    /// Quote shown by the screensaver, changes every few seconds
+    fn get_idle_quote(&self) -> &str {
+        let quotes = &self.settings.idle_quotes;
+        if quotes.is_empty() {
+            return "";
+        }
+        let index = self.last_event.elapsed().as_secs() / QUOTE_DURATION.as_secs();
+        &quotes[index as usize % quotes.len()]
+    }
+
+    /// Throws away the warm-up text so the actual session starts fresh
+    fn end_warmup(&mut self) {
+        self.warming_up = false;
+        self.last_keystroke = None;
+        self.word_samples.clear();
+        self.wpm_window_start = Instant::now();
+        self.writing_time.reset();
+        self.text.clear();
+        self.ratchet_floor = 0;
+        self.apply_existing_text();
+    }
+
+    /// Starts measuring the words per minute anew
+    fn restart_wpm_window(&mut self) {
+        self.word_samples.clear();
+        self.wpm_window_start = Instant::now();
+        self.wpm_below_since = None;
+    }
+
+    /// Deletes the text back to the ratchet floor as a penalty for not writing
+    fn wipe_text(&mut self) {
+        self.last_keystroke = None;
+        self.wipe_pending_since = None;
+        self.restart_wpm_window();
+        if self.settings.reset_time_on_timeout {
+            self.writing_time.reset();
         }
+        self.text.truncate(self.ratchet_floor);
+        self.clean_run_start = Some(self.get_word_count());
+    }
+
+    /// Turns the current line into a heading or moves it to the next heading
+    /// level. Headings start one level below the title and are removed again
+    /// after the deepest level.
+    fn cycle_heading(&mut self) {
+        let format = self.settings.output_format;
+        let Some(marker) = format.heading_marker() else {
+            return;
+        };
+        let line_start = self.text.rfind('\n').map_or(0, |index| index + 1);
+        let current_level = format.heading_level(&self.text[line_start..]);
+        let mut removed = 0;
+        if let Some(level) = current_level {
+            removed = level + 1;
+            self.text
+                .replace_range(line_start..line_start + removed, "");
+        }
+        let first_level = format
+            .heading_level(&self.title)
+            .map_or(1, |level| level + 1);
+        let next_level = current_level.map_or(first_level, |level| level + 1);
+        let mut inserted = 0;
+        if next_level <= MAX_HEADING_LEVEL {
+            let prefix = format!("{} ", marker.to_string().repeat(next_level));
+            inserted = prefix.len();
+            self.text.insert_str(line_start, &prefix);
+        }
+        // Keep the ratchet floor behind the same text it protected before
+        if line_start < self.ratchet_floor {
+            let floor = max(self.ratchet_floor.saturating_sub(removed), line_start);
+            self.ratchet_floor = min(floor + inserted, self.text.len());
+        }
+    }
+
+    /// Resets the keystroke timeout and starts the writing time if needed
+    fn register_keystroke(&mut self) {
+        self.last_keystroke = Some(Instant::now());
+        self.scroll_offset = 0;
+        if !self.writing_time.is_running() {
+            self.writing_time.start();
+        }
+    }
+
+    /// Whether the text view can be scrolled up through the text
+    fn can_scroll(&self) -> bool {
+        self.settings.scroll_enabled || self.achieved_goals()
+    }
+
+    /// Scrolls the text view by the given number of lines, negative values
+    /// scroll up towards the beginning of the text
+    fn scroll(&mut self, lines: isize) {
+        let (paragraph_rows, paragraph_cols) = self.paragraph_size.get();
+        let shown_text = if self.recent_entries_visible() {
+            &self.recent_entries
+        } else {
+            &self.text
+        };
+        let total_lines = textwrap::fill(shown_text, get_wrap_options(paragraph_cols))
+            .lines()
+            .count();
+        let max_offset = get_skipped_lines(total_lines, paragraph_rows);
+        self.scroll_offset = self
+            .scroll_offset
+            .saturating_add_signed(-lines)
+            .min(max_offset);
+    }
+
+    /// Number of wrapped lines hidden above the text view
+    fn get_first_visible_line(&self, total_lines: usize, paragraph_rows: usize) -> usize {
+        get_skipped_lines(total_lines, paragraph_rows).saturating_sub(self.scroll_offset)
+    }
+
+    /// Turns off strict mode for the rest of the session once the request is
+    /// confirmed by asking again within a few seconds
+    fn request_strict_off(&mut self) {
+        if !self.settings.strict_mode || self.achieved_goals() {
+            return;
+        }
+        let confirmed = self
+            .strict_off_requested
+            .is_some_and(|since| since.elapsed() < STATUS_MESSAGE_DURATION);
+        if confirmed {
+            self.settings.strict_mode = false;
+            self.strict_off_requested = None;
+            self.set_status_message("Strict mode is off for this session.".to_string());
+        } else {
+            self.strict_off_requested = Some(Instant::now());
+            self.set_status_message(
+                "Press Ctrl+Q again to turn off strict mode for this session.".to_string(),
+            );
+        }
+    }
+
+    fn get_theme(&self) -> &Theme {
+        &self.settings.themes[self.theme_index]
+    }
+
+    /// Switches to the next of the configured themes
+    fn next_theme(&mut self) {
+        self.theme_index = (self.theme_index + 1) % self.settings.themes.len();
+        let message = format!("Theme: {}", self.get_theme().name);
+        self.set_status_message(message);
+    }
+
+    /// Style for text in the given color, applying `passive_dim_level` to passive text
+    fn get_style(&self, color: Color) -> Style {
+        let theme = self.get_theme();
+        if color != theme.passive {
+            return Style::default().fg(color);
+        }
+        match self.settings.passive_dim_level.as_str() {
+            "dim" => Style::default()
+                .fg(theme.passive)
+                .add_modifier(Modifier::DIM),
+            "dark" => Style::default().fg(Color::DarkGray),
+            "bright" => Style::default().fg(Color::White),
+            _ => Style::default().fg(theme.passive),
+        }
+    }
+
+    /// Title, value and color of each block in the stats row
+    fn get_stats(&self) -> Vec<(&'static str, String, Color)> {
+        let mut stats = vec![
+            (
+                "Word count",
+                self.get_word_count_string(),
+                self.get_word_count_color(),
+            ),
+            (
+                "Character count",
+                self.get_character_count_string(),
+                self.get_character_count_color(),
+            ),
+            ("Time", self.get_time_string(), self.get_time_color()),
+        ];
+        if self.settings.show_paragraph_words {
+            stats.push((
+                "Paragraph",
+                format!("¶ {}", self.get_paragraph_word_count()),
+                self.get_theme().passive,
+            ));
+        }
+        if self.settings.char_limit.is_some() {
+            stats.push((
+                "Limit",
+                self.get_char_remaining_string(),
+                self.get_char_remaining_color(),
+            ));
+        }
+        if self.settings.show_sentence_stats {
+            let (sentences, average) = self.get_sentence_stats();
+            let theme = self.get_theme();
+            let color = match self.settings.sentence_length_warning {
+                Some(limit) if average > limit as f32 => theme.warning,
+                _ => theme.passive,
+            };
+            stats.push((
+                "Sentences",
+                format!(
+                    "{} (⌀ {average:.1} words)",
+                    self.format_count(sentences as i64)
+                ),
+                color,
+            ));
+        }
+        stats
+    }
+
+    /// Number of sentences and the average number of words per sentence.
+    /// Sentences end with `.`, `!` or `?` unless the word is a common
+    /// abbreviation or an initial.
+    fn get_sentence_stats(&self) -> (usize, f32) {
+        let mut sentences = 0;
+        let mut words = 0;
+        let mut open_sentence = false;
+        for word in self.text.split_whitespace() {
+            words += 1;
+            open_sentence = true;
+            let word = word.trim_end_matches(['"', '\'', ')', ']', '”', '’']);
+            let is_abbreviation = word.ends_with('.')
+                && (ABBREVIATIONS.contains(&word.to_lowercase().as_str())
+                    || (word.chars().count() == 2
+                        && word.chars().next().is_some_and(char::is_uppercase)));
+            if word.ends_with(['.', '!', '?']) && !is_abbreviation {
+                sentences += 1;
+                open_sentence = false;
+            }
+        }
+        if open_sentence {
+            sentences += 1;
+        }
+        let average = if sentences > 0 {
+            words as f32 / sentences as f32
+        } else {
+            0.0
+        };
+        (sentences, average)
+    }
+
+    /// Number of words since the last blank line
+    fn get_paragraph_word_count(&self) -> usize {
+        let mut word_count = 0;
+        for (index, line) in self.text.split('\n').rev().enumerate() {
+            if index > 0 && line.trim().is_empty() {
+                break;
+            }
+            word_count += count_words(line, self.settings.word_count_mode);
+        }
+        word_count
+    }
+
+    /// Whether the stats are shown, they can be hidden during a writing session
+    fn stats_visible(&self) -> bool {
+        let writing = matches!(self.input_mode, InputMode::Writing) || self.editing_title;
+        !(self.settings.hide_stats_while_writing && writing)
+    }
+
+    fn set_status_message(&mut self, message: String) {
+        self.status_message = Some((Instant::now(), message));
+    }
+
+    /// File the current entry is saved to while the session is still running
+    fn get_checkpoint_file(&self) -> String {
+        format!("{}{CHECKPOINT_SUFFIX}", self.output_file)
+    }
+
+    /// Overwrites the checkpoint file with the current entry
+    fn save_checkpoint(&self) -> io::Result<()> {
+        let mut output = std::fs::File::create(self.get_checkpoint_file())?;
+        write_entry(&mut output, self)
+    }
+
+    /// Replaces the last space of the current line with a line break if the
+    /// line is longer than `hard_wrap_live`. Since the line break takes the
+    /// place of the space, deleting it with backspace joins the lines again.
+    fn hard_wrap(&mut self) {
+        let Some(column) = self.settings.hard_wrap_live else {
+            return;
+        };
+        let line_start = self.text.rfind('\n').map_or(0, |index| index + 1);
+        let line = &self.text[line_start..];
+        if line.width() as i64 <= column {
+            return;
+        }
+        if let Some(space) = line.rfind(' ') {
+            self.text
+                .replace_range(line_start + space..line_start + space + 1, "\n");
+        }
+    }
+
+    /// Whether the next character typed is the first one of a sentence
+    fn starts_sentence(&self) -> bool {
+        let before = self.text.trim_end();
+        let after_boundary = before.is_empty() || before.ends_with(['.', '!', '?']);
+        after_boundary && (self.text.is_empty() || self.text.ends_with(char::is_whitespace))
+    }
+
+    /// Line recording that the goals of the session were reached
+    fn get_goal_badge(&self) -> Option<String> {
+        let has_goal = self.settings.word_goal.is_some() || self.settings.time_goal.is_some();
+        if !self.settings.record_goal_badge || !has_goal || !self.achieved_goals() {
+            return None;
+        }
+        let words = self.get_word_count();
+        let minutes = self.writing_time.elapsed().as_secs() / 60;
+        Some(
+            self.settings
+                .goal_badge_format
+                .replace("{words}", &words.to_string())
+                .replace("{minutes}", &minutes.to_string()),
+        )
+    }
+
+    fn has_text(&self) -> bool {
+        !self.text.is_empty()
+    }
+
+    /// Whether the text box shows the `recent_entries` instead of the text
+    fn recent_entries_visible(&self) -> bool {
+        matches!(self.input_mode, InputMode::Title)
+            && !self.editing_title
+            && !self.has_text()
+            && !self.recent_entries.is_empty()
+    }
+
+    /// Whether there is anything to save at the end of the session
+    fn has_entry(&self) -> bool {
+        self.has_text() || (self.settings.save_title_only && !self.title.is_empty())
+    }
+
+    fn get_paragraph_text(&self, paragraph_rows: usize, paragraph_cols: usize) -> String {
+        let wrapped_text = textwrap::fill(&self.text, get_wrap_options(paragraph_cols));
+
+        let total_lines = wrapped_text.lines().count();
+        let mut final_text = String::default();
+        let skip = self.get_first_visible_line(total_lines, paragraph_rows);
+        let line_iterator = wrapped_text.lines().skip(skip);
+        for line in line_iterator {
+            final_text = format!("{}{}\n", final_text, line);
+        }
+        final_text = final_text.trim_end_matches(char::is_whitespace).to_string();
+        let trailing_whitespace =
+            &self.text[self.text.trim_end_matches(char::is_whitespace).len()..];
+        if !trailing_whitespace.is_empty() && self.scroll_offset == 0 {
+            // Trailing whitespace is discarded by
+            // `textwrap::wrap`. We reinsert it here. If multiple
+            // spaces are added, this can overflow the margins
+            // which look a bit odd. Handling this would require
+            // some more tinkering...
+            final_text = format!("{}{}", final_text, trailing_whitespace);
+        };
+        final_text
+    }
+
+    /// For each line returned by `get_paragraph_text` whether its logical
+    /// line is shorter than `line_min` or longer than `line_max`
+    fn get_line_warnings(&self, paragraph_rows: usize, paragraph_cols: usize) -> Vec<bool> {
+        if self.settings.line_min.is_none() && self.settings.line_max.is_none() {
+            return Vec::new();
+        }
+        let logical_lines: Vec<&str> = self.text.split('\n').collect();
+        let mut warnings = Vec::new();
+        for (index, line) in logical_lines.iter().enumerate() {
+            let length = line.chars().count() as i64;
+            // The line currently being written cannot be too short yet
+            let is_last = index + 1 == logical_lines.len();
+            let too_short = !line.is_empty()
+                && !is_last
+                && self.settings.line_min.is_some_and(|min| length < min);
+            let too_long = self.settings.line_max.is_some_and(|max| length > max);
+            let display_lines = max(
+                1,
+                textwrap::wrap(line, get_wrap_options(paragraph_cols)).len(),
+            );
+            warnings.resize(warnings.len() + display_lines, too_short || too_long);
+        }
+        let total_lines = textwrap::fill(&self.text, get_wrap_options(paragraph_cols))
+            .lines()
+            .count();
+        let skip = self.get_first_visible_line(total_lines, paragraph_rows);
+        warnings.into_iter().skip(skip).collect()
+    }
+}
+
+fn get_wrap_options(paragraph_cols: usize) -> textwrap::Options<'static> {
+    textwrap::Options::new(paragraph_cols)
+        .word_separator(textwrap::WordSeparator::UnicodeBreakProperties)
+}
+
+/// Number of wrapped lines to skip so that the end of the text is visible
+fn get_skipped_lines(total_lines: usize, paragraph_rows: usize) -> usize {
+    if total_lines > max(1, paragraph_rows) - 1 {
+        total_lines - paragraph_rows + 1
+    } else {
+        0
+    }
+}
+
+/// Column at which a line of the given width starts, computed the same way as
+/// tui does for aligned paragraphs
+fn get_line_offset(alignment: Alignment, line_width: u16, area_width: u16) -> u16 {
+    match alignment {
+        Alignment::Left => 0,
+        Alignment::Center => (area_width / 2).saturating_sub(line_width / 2),
+        Alignment::Right => area_width.saturating_sub(line_width),
+    }
+}
+
+fn get_text_position(text: &str) -> (u16, u16) {
+    let last_line = text.lines().last().unwrap_or_default();
+    let last_line_offset = usize::from(text.ends_with('\n'));
+    let line_position = if last_line_offset == 1 {
+        0
+    } else {
+        last_line.width() as u16
+    };
+    (
+        line_position,
+        max(1, text.lines().count() + last_line_offset) as u16,
+    )
+}
+
+/// Reads the last `count` entries of a file. An entry starts with a heading of
+/// the same level as the title. Without such headings the end of the file is
+/// returned as a single entry.
+fn read_recent_entries(path: &Path, count: usize, format: OutputFormat, title: &str) -> String {
+    let contents = std::fs::read_to_string(path).unwrap_or_default();
+    let entry_starts = get_entry_starts(&contents, format, title);
+    let start = match entry_starts.len().checked_sub(count) {
+        Some(index) => entry_starts.get(index).copied().unwrap_or(0),
+        None => 0,
+    };
+    contents[start..].trim_end().to_string()
+}
+
+/// Offsets of the lines starting an entry, which are headings of the same
+/// level as the title. Empty if the title is no heading.
+fn get_entry_starts(contents: &str, format: OutputFormat, title: &str) -> Vec<usize> {
+    let Some(level) = format.heading_level(title) else {
+        return Vec::new();
+    };
+    let mut entry_starts = Vec::new();
+    let mut offset = 0;
+    for line in contents.split_inclusive('\n') {
+        if format.heading_level(line) == Some(level) {
+            entry_starts.push(offset);
+        }
+        offset += line.len();
+    }
+    entry_starts
+}
+
+/// Last entry of a file, continued with --continue-last
+struct ContinuedEntry {
+    path: String,
+    /// Offset of the entry in the file
+    start: usize,
+    title: String,
+    text: String,
+}
+
+/// Splits the last entry off the contents of a file. The first line of the
+/// entry is its title, the rest its text without the goal badge and note that
+/// are added again when saving.
+fn split_last_entry(
+    contents: &str,
+    settings: &Settings,
+    title: &str,
+) -> Option<(usize, String, String)> {
+    let format = settings.output_format;
+    let start = *get_entry_starts(contents, format, title).last()?;
+    let (entry_title, text) = contents[start..]
+        .split_once('\n')
+        .unwrap_or((&contents[start..], ""));
+    let mut text = text.trim();
+    loop {
+        let (rest, last_line) = text.rsplit_once('\n').unwrap_or(("", text));
+        let last_line = last_line.trim();
+        if last_line.is_empty()
+            || !(format.is_comment(last_line)
+                || matches_badge_format(last_line, &settings.goal_badge_format))
+        {
+            break;
+        }
+        text = rest.trim_end();
+    }
+    Some((start, entry_title.trim_end().to_string(), text.to_string()))
+}
+
+/// Whether the line is a goal badge written with the `goal_badge_format`
+fn matches_badge_format(line: &str, badge_format: &str) -> bool {
+    if badge_format.is_empty() {
+        return false;
+    }
+    let pattern = badge_format
+        .replace("{words}", "\0")
+        .replace("{minutes}", "\0");
+    let mut rest = line;
+    for (index, literal) in pattern.split('\0').enumerate() {
+        if index > 0 {
+            let number = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+            if number.len() == rest.len() {
+                return false;
+            }
+            rest = number;
+        }
+        match rest.strip_prefix(literal) {
+            Some(after) => rest = after,
+            None => return false,
+        }
+    }
+    rest.is_empty()
+}
+
+/// Finds the last entry of the most recently modified file named after `file_string`
+fn find_last_entry(
+    settings: &Settings,
+    now: DateTime<Utc>,
+    title: &str,
+) -> Result<ContinuedEntry, WritingBuddyError> {
+    let (directory, pattern) = get_output_directory(settings, now);
+    let latest = list_output_files(&directory, &pattern)
+        .into_iter()
+        .filter_map(|(path, _)| {
+            let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+            Some((modified, path))
+        })
+        .max_by_key(|(modified, _)| *modified);
+    let Some((_, path)) = latest else {
+        return Err(WritingBuddyError::NoDraftFound(
+            directory.display().to_string(),
+        ));
+    };
+    let path = path.display().to_string();
+    let contents = std::fs::read_to_string(&path).unwrap_or_default();
+    let (start, title, text) = split_last_entry(&contents, settings, title)
+        .ok_or_else(|| WritingBuddyError::NoEntryFound(path.clone()))?;
+    Ok(ContinuedEntry {
+        path,
+        start,
+        title,
+        text,
+    })
+}
+
+/// Directory of the current output file and the `file_string` pattern of the
+/// file names in it
+fn get_output_directory(settings: &Settings, now: DateTime<Utc>) -> (PathBuf, String) {
+    let current_file = PathBuf::from(now.format(&settings.file_string).to_string());
+    let pattern = Path::new(&settings.file_string)
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let directory = match current_file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    (directory, pattern)
+}
+
+/// Files in the directory whose name matches the pattern, with their date
+fn list_output_files(directory: &Path, pattern: &str) -> Vec<(PathBuf, (i32, u32, u32))> {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| {
+            let period = parse_file_period(&entry.file_name().to_string_lossy(), pattern)?;
+            Some((entry.path(), period))
+        })
+        .collect()
+}
+
+/// Moves files named after `file_string` whose date lies before the current
+/// file into the `archive_dir`, except `keep` which is continued with
+/// --continue-last. With `archive_dry_run` the files are only listed.
+fn archive_old_files(
+    settings: &Settings,
+    now: DateTime<Utc>,
+    keep: Option<&Path>,
+    messages: &mut StartupMessages,
+) {
+    let (directory, pattern) = get_output_directory(settings, now);
+    let current_file = PathBuf::from(now.format(&settings.file_string).to_string());
+    let current_name = current_file
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let Some(current_period) = parse_file_period(&current_name, &pattern) else {
+        return;
+    };
+    let archive_dir = directory.join(&settings.archive_dir);
+    for (path, period) in list_output_files(&directory, &pattern) {
+        if period >= current_period || keep.is_some_and(|keep| keep == path) {
+            continue;
+        }
+        let name = path.file_name().unwrap_or_default();
+        let target = archive_dir.join(name);
+        if settings.archive_dry_run {
+            messages.notice(format!(
+                "Would archive {} to {}",
+                path.display(),
+                target.display()
+            ));
+            continue;
+        }
+        if target.exists() {
+            messages.notice(format!(
+                "Not archiving {} because {} already exists",
+                path.display(),
+                target.display()
+            ));
+            continue;
+        }
+        let result =
+            std::fs::create_dir_all(&archive_dir).and_then(|_| std::fs::rename(&path, &target));
+        match result {
+            Ok(()) => messages.notice(format!(
+                "Archived {} to {}",
+                path.display(),
+                target.display()
+            )),
+            Err(err) => messages.notice(format!("Failed to archive {}: {err}", path.display())),
+        }
+    }
+}
+
+/// Year, month and day of a file name created from the `file_string` pattern.
+/// Parts missing in the pattern are 1.
+fn parse_file_period(name: &str, pattern: &str) -> Option<(i32, u32, u32)> {
+    let mut parsed = Parsed::new();
+    chrono::format::parse(&mut parsed, name, StrftimeItems::new(pattern)).ok()?;
+    Some((
+        parsed.year?,
+        parsed.month.unwrap_or(1),
+        parsed.day.unwrap_or(1),
+    ))
+}
+
+/// Counts the words of the text
+fn count_words(text: &str, mode: WordCountMode) -> usize {
+    let markdown = match mode {
+        WordCountMode::Plain => false,
+        WordCountMode::Markdown => true,
+        WordCountMode::Auto => looks_like_markdown(text),
+    };
+    if !markdown {
+        return text.split_whitespace().count();
+    }
+    text.lines()
+        .flat_map(|line| strip_markdown_prefix(line).split_whitespace())
+        // Only the text of a link counts, not its target
+        .map(|token| token.split("](").next().unwrap_or_default())
+        .filter(|token| token.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+/// Whether any line starts with a heading, list or quote marker or contains a
+/// link. Only complete markers count so the result does not change while a
+/// word is typed.
+fn looks_like_markdown(text: &str) -> bool {
+    text.lines().any(|line| {
+        strip_markdown_prefix(line).len() != line.trim_start().len() || line.contains("](")
+    })
+}
+
+/// The line without leading heading, list and quote markers
+fn strip_markdown_prefix(line: &str) -> &str {
+    let mut rest = line.trim_start();
+    loop {
+        let heading = rest.trim_start_matches('#');
+        let numbered = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+        let stripped = if heading.len() < rest.len() && heading.starts_with(' ') {
+            heading
+        } else if numbered.len() < rest.len() && numbered.starts_with(". ") {
+            &numbered[1..]
+        } else if let Some(marker) = ["> ", "- ", "* ", "+ "]
+            .iter()
+            .find(|marker| rest.starts_with(*marker))
+        {
+            &rest[marker.len() - 1..]
+        } else {
+            return rest;
+        };
+        rest = stripped.trim_start();
+    }
+}
+
+/// Counts the words of a file, ignoring headings and comments
+fn count_words_in_file(path: &Path, format: OutputFormat) -> usize {
+    std::fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| format.heading_level(line).is_none() && !format.is_comment(line))
+        .map(|line| line.split_whitespace().count())
+        .sum()
+}
+
+/// Counts the words of all files below `directory` whose name matches `pattern`
+fn count_project_words(directory: &Path, pattern: &str, format: OutputFormat) -> usize {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Symbolic links to directories are not followed, they could lead in a circle
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                count_project_words(&path, pattern, format)
+            } else if matches_glob(&name, pattern) && !name.ends_with(CHECKPOINT_SUFFIX) {
+                count_words_in_file(&path, format)
+            } else {
+                0
+            }
+        })
+        .sum()
+}
+
+/// Whether the name matches a pattern where `*` stands for any number of
+/// characters and `?` for a single character
+fn matches_glob(name: &str, pattern: &str) -> bool {
+    let name: Vec<char> = name.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+    // Position after the last `*` in the pattern and the name position it matched up to
+    let mut backtrack = None;
+    let (mut n, mut p) = (0, 0);
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            n += 1;
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            p += 1;
+            backtrack = Some((p, n));
+        } else if let Some((star_p, star_n)) = backtrack {
+            p = star_p;
+            n = star_n + 1;
+            backtrack = Some((star_p, n));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn create_config_file(config_path: &Path, messages: &mut StartupMessages) -> bool {
+    if let Some(directory) = config_path.parent() {
+        if std::fs::create_dir_all(directory).is_err() {
+            return false;
+        }
+    }
+    let config_contents = include_str!("../default_config.toml");
+    messages.notice(format!("Writing default config to: {:?}", config_path));
+    if std::fs::write(config_path, config_contents).is_ok() {
+        return true;
+    }
+    false
+}
+
+/// File the text of a session started at `now` is written to
+fn get_filename(settings: &Settings, append_to: Option<String>, now: DateTime<Utc>) -> String {
+    match append_to {
+        Some(append_to) => append_to,
+        None => now.format(&settings.file_string).to_string(),
+    }
+}
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), WritingBuddyError> {
+    let cli_config = CliConfig::from_args();
+
+    let append_to = cli_config.append_to.clone();
+    let no_title = cli_config.no_title;
+    let print_filename = cli_config.print_filename;
+    // Stdin has to be read before the terminal is set up
+    let piped_text = if cli_config.edit_stdin {
+        if io::stdin().is_terminal() {
+            return Err(WritingBuddyError::NoPipedInput);
+        }
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .map_err(WritingBuddyError::PipedInput)?;
+        Some(text)
+    } else {
+        None
+    };
+    let mut messages = StartupMessages::default();
+    let config = match get_settings(&cli_config, &mut messages) {
+        Ok(config) => config,
+        Err(err) => {
+            messages.print(true);
+            let use_defaults = match ConfigErrorPolicy::from_name(&cli_config.on_config_error) {
+                ConfigErrorPolicy::Exit => false,
+                ConfigErrorPolicy::Defaults => {
+                    eprintln!("{err}");
+                    true
+                }
+                ConfigErrorPolicy::Prompt => {
+                    eprintln!("{err}");
+                    if !confirm_default_settings() {
+                        // The error was already shown with the question
+                        std::process::exit(1);
+                    }
+                    true
+                }
+            };
+            if !use_defaults {
+                return Err(err);
+            }
+            eprintln!("Using the default settings.");
+            Config::default()
+        }
+    };
+    let mut settings = Settings::from_config(&config);
+    settings.skip_title |= no_title;
+    let now = Utc::now();
+    let title = now.format(&settings.title_string).to_string();
+    // The lookup comes before archiving, the file to continue may be from an earlier period
+    let continued = if cli_config.continue_last {
+        match find_last_entry(&settings, now, &title) {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                messages.print(false);
+                return Err(err);
+            }
+        }
+    } else {
+        None
+    };
+    if settings.auto_archive && !print_filename {
+        let keep = continued.as_ref().map(|entry| Path::new(&entry.path));
+        archive_old_files(&settings, now, keep, &mut messages);
+    }
+    // Without quiet_startup the messages are shown right away, otherwise the
+    // important ones are shown once the terminal is restored
+    if !settings.quiet_startup {
+        messages.print(true);
+    }
+    let filename = match &continued {
+        Some(entry) => entry.path.clone(),
+        None => get_filename(&settings, append_to, now),
+    };
+    if print_filename {
+        messages.print(false);
+        println!("{filename}");
+        return Ok(());
+    }
+    if Path::new(&filename).is_dir() {
+        messages.print(false);
+        return Err(WritingBuddyError::OutputIsDirectory(filename));
+    }
+
+    // setup terminal
+    enable_raw_mode().map_err(WritingBuddyError::Terminal)?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen).map_err(WritingBuddyError::Terminal)?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend).map_err(WritingBuddyError::Terminal)?;
+
+    let mut app = App::new(title, filename.clone(), settings);
+    if let Some(text) = &piped_text {
+        app.load_existing_text(text);
+    }
+    if let Some(entry) = continued {
+        app.title = entry.title;
+        app.continued_entry_start = Some(entry.start);
+        app.load_existing_text(&entry.text);
+    }
+    if let Some(count) = app.settings.show_recent_entries {
+        app.recent_entries = read_recent_entries(
+            Path::new(&filename),
+            usize::try_from(count).unwrap_or(0),
+            app.settings.output_format,
+            &app.title,
+        );
+    }
+    if app.settings.daily_target.is_some() {
+        app.written_words = count_words_in_file(Path::new(&filename), app.settings.output_format);
+    }
+    if let Some(project_dir) = &app.settings.project_dir {
+        app.project_words = Some(count_project_words(
+            Path::new(project_dir),
+            &app.settings.project_glob,
+            app.settings.output_format,
+        ));
+    }
+    if app.continued_entry_start.is_some() {
+        // The continued entry is already in the file and counted again as the text
+        app.written_words = app.written_words.saturating_sub(app.existing_words);
+        app.project_words = app
+            .project_words
+            .map(|words| words.saturating_sub(app.existing_words));
+    }
+    let res = run_app(&mut terminal, &mut app);
+
+    // restore terminal, all steps are tried and the first error is returned after saving
+    let restore_error = [
+        disable_raw_mode(),
+        execute!(terminal.backend_mut(), LeaveAlternateScreen),
+        terminal.show_cursor(),
+    ]
+    .into_iter()
+    .find_map(Result::err);
+    messages.print(false);
+
+    if app.has_entry() {
+        println!("Storing text into: {}", &filename);
+        match save_entry(Path::new(&filename), &app) {
+            Ok(()) => {
+                // The checkpoint is not needed anymore once the entry is stored
+                let _ = std::fs::remove_file(app.get_checkpoint_file());
+                if let Some(csv_log) = &app.settings.csv_log {
+                    if let Err(err) = write_csv_log(Path::new(csv_log), &app) {
+                        println!("Warning: failed to write the session to {csv_log:?}: {err}");
+                    }
+                }
+                // Last, so that a slow command does not hold up the bookkeeping
+                if let Some(command) = &app.settings.on_complete_cmd {
+                    run_complete_command(command, &filename, &app);
+                }
+            }
+            Err(err) => {
+                println!("{err}");
+                save_panic_file(&app);
+            }
+        }
+    }
+
+    // The text is stored first so that a terminal error cannot lose it
+    res.and(restore_error.map_or(Ok(()), Err))
+        .map_err(WritingBuddyError::Terminal)
+}
+
+/// Writes the title, text and note of the current session
+fn write_entry(output: &mut impl Write, app: &App) -> io::Result<()> {
+    if !app.title.is_empty() {
+        writeln!(output, "{}", app.title)?;
+    }
+    if app.has_text() {
+        writeln!(output, "{}", app.text.trim_end())?;
+    }
+    if let Some(badge) = app.get_goal_badge() {
+        writeln!(output, "{badge}")?;
+    }
+    if !app.note.is_empty() {
+        writeln!(
+            output,
+            "{}",
+            app.settings.output_format.format_note(&app.note)
+        )?;
+    }
+    for _ in 0..app.settings.entry_trail_blank {
+        writeln!(output)?;
     }
+    Ok(())
+}
 
-    fn has_text(&self) -> bool {
-        !self.text.is_empty()
+/// Line breaks to write before an entry appended to `contents` so that it is
+/// separated by `lead_blank` blank lines, counting the blank lines that are
+/// already at the end. Nothing is needed at the start of a file.
+fn get_entry_separator(contents: &str, lead_blank: usize) -> String {
+    if contents.is_empty() {
+        return String::new();
     }
+    let trailing = &contents[contents.trim_end().len()..];
+    let line_breaks = trailing.matches('\n').count();
+    let missing_line_break = usize::from(line_breaks == 0);
+    let blank_lines = line_breaks.saturating_sub(1);
+    "\n".repeat(missing_line_break + lead_blank.saturating_sub(blank_lines))
+}
 
-    fn get_paragraph_text(&self, paragraph_rows: usize, paragraph_cols: usize) -> String {
-        let wrapped_text = textwrap::fill(&self.text, paragraph_cols);
+/// Loads a config file and warns if it does not contain any known setting
+fn load_config_file(
+    config_file: &str,
+    messages: &mut StartupMessages,
+) -> Result<Config, config::ConfigError> {
+    let settings = Config::builder()
+        .add_source(config::File::with_name(config_file))
+        .build()?;
+    if !CONFIG_KEYS
+        .iter()
+        .any(|key| settings.get::<config::Value>(key).is_ok())
+    {
+        messages.notice(format!(
+            "Warning: config file {:?} does not contain any known setting. Known settings are: {}",
+            config_file,
+            CONFIG_KEYS.join(", ")
+        ));
+    }
+    Ok(settings)
+}
 
-        let total_lines = wrapped_text.lines().count();
-        let mut final_text = String::default();
-        let skip = if total_lines > max(1, paragraph_rows) - 1 {
-            total_lines - paragraph_rows + 1
-        } else {
-            0
-        };
-        let line_iterator = wrapped_text.lines().skip(skip);
-        for line in line_iterator {
-            final_text = format!("{}{}\n", final_text, line);
+/// Last resort when saving failed: writes the entry to the temp directory
+fn save_panic_file(app: &App) {
+    let filename = format!(
+        "writingbuddy-{}.{}",
+        Utc::now().format("%Y-%m-%d-%H%M%S"),
+        app.settings.output_format.extension()
+    );
+    let path = std::env::temp_dir().join(filename);
+    let result = std::fs::File::create(&path).and_then(|mut output| write_entry(&mut output, app));
+    match result {
+        Ok(()) => {
+            println!();
+            println!("!!! Your text was saved to {} instead !!!", path.display());
+            println!();
+        }
+        Err(err) => {
+            println!("Failed to save your text to {}: {err}", path.display());
+            println!("{}", app.title);
+            println!("{}", app.text);
         }
-        final_text = final_text.trim_end_matches(char::is_whitespace).to_string();
-        let trailing_whitespace =
-            &self.text[self.text.trim_end_matches(char::is_whitespace).len()..];
-        if !trailing_whitespace.is_empty() {
-            // Trailing whitespace is discarded by
-            // `textwrap::wrap`. We reinsert it here. If multiple
-            // spaces are added, this can overflow the margins
-            // which look a bit odd. Handling this would require
-            // some more tinkering...
-            final_text = format!("{}{}", final_text, trailing_whitespace);
-        };
-        final_text
     }
 }
 
-fn get_text_position(text: &str) -> (u16, u16) {
-    let last_line = text.lines().last().unwrap_or_default();
-    let last_line_offset = usize::from(text.ends_with('\n'));
-    let line_position = if last_line_offset == 1 {
-        0
+/// Appended to the output file name for the checkpoint saved with Ctrl+S
+const CHECKPOINT_SUFFIX: &str = ".checkpoint";
+
+const CSV_HEADER: &str = "date,title,words,seconds,wpm,goal_met";
+
+/// Appends a row with the stats of the session to the `csv_log`
+fn write_csv_log(path: &Path, app: &App) -> io::Result<()> {
+    let row = format_csv_row(
+        &Utc::now().format("%Y-%m-%d").to_string(),
+        &app.title,
+        app.get_word_count(),
+        app.writing_time.elapsed().as_secs(),
+        app.achieved_goals(),
+    );
+    append_csv_row(path, &row)
+}
+
+/// One row of the `csv_log`, the words per minute are computed from the
+/// words and seconds
+fn format_csv_row(date: &str, title: &str, words: usize, seconds: u64, goal_met: bool) -> String {
+    let wpm = if seconds > 0 {
+        words as f64 * 60.0 / seconds as f64
     } else {
-        last_line.chars().count() as u16
+        0.0
     };
-    (
-        line_position,
-        max(1, text.lines().count() + last_line_offset) as u16,
+    format!(
+        "{},{},{words},{seconds},{wpm:.1},{goal_met}",
+        escape_csv_field(date),
+        escape_csv_field(title)
     )
 }
 
-fn create_default_config() -> bool {
-    if let Some(config_dir) = dirs::config_dir() {
-        let config_directory = config_dir.join("writingbuddy/");
-        let config_file = config_directory.join("writingbuddy.toml");
-        return create_config_file(&config_file);
+/// Appends the row to a CSV file. The header is written when the file is
+/// created or empty.
+fn append_csv_row(path: &Path, row: &str) -> io::Result<()> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
     }
-    false
+    let is_new = std::fs::metadata(path).map_or(true, |metadata| metadata.len() == 0);
+    let mut output = OpenOptions::new().append(true).create(true).open(path)?;
+    if is_new {
+        writeln!(output, "{CSV_HEADER}")?;
+    }
+    writeln!(output, "{row}")
 }
 
-fn create_config_file(config_path: &Path) -> bool {
-    if let Some(directory) = config_path.parent() {
-        if std::fs::create_dir_all(directory).is_err() {
-            return false;
-        }
-    }
-    let config_contents = include_str!("../default_config.toml");
-    println!("Writing default config to: {:?}", config_path);
-    if std::fs::write(config_path, config_contents).is_ok() {
-        return true;
+/// Quotes a CSV field if it contains a separator, quote or line break
+fn escape_csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
     }
-    false
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let cli_config = CliConfig::from_args();
-
-    let settings = get_settings(cli_config);
-
-    let now = Utc::now();
-    let title_format_str = settings
-        .get_string("title_string")
-        .unwrap_or_else(|_| "## %Y-%m-%d".to_string());
-    let title = now.format(&title_format_str);
-    let file_format_str = settings
-        .get_string("file_string")
-        .unwrap_or_else(|_| "%Y-%m.md".to_string());
-    let filename = now.format(&file_format_str);
-    let backspace_active = settings.get_bool("backspace_active").unwrap_or(true);
-    let mut time_goal = settings.get_int("time_goal").ok();
-    if let Some(0) = time_goal {
-        time_goal = None;
-    }
-    let mut word_goal = settings.get_int("word_goal").ok();
-    if let Some(0) = word_goal {
-        word_goal = None;
-    }
-    let mut character_goal = settings.get_int("character_goal").ok();
-    if let Some(0) = character_goal {
-        character_goal = None;
-    }
-    let mut keystroke_timeout = settings.get_int("keystroke_timeout").ok();
-    if let Some(0) = keystroke_timeout {
-        keystroke_timeout = None;
-    }
-    let strict_mode = settings.get_bool("strict_mode").unwrap_or(true);
-
-    // setup terminal
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
-
-    let mut app = App::new(
-        title.to_string(),
-        time_goal,
-        word_goal,
-        character_goal,
-        backspace_active,
-        strict_mode,
-        keystroke_timeout,
-    );
-    let res = run_app(&mut terminal, &mut app);
+/// Runs the `on_complete_cmd` with details about the session in environment variables
+fn run_complete_command(command: &str, filename: &str, app: &App) {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let status = Command::new(shell)
+        .args([flag, command])
+        .env("WRITINGBUDDY_FILE", filename)
+        .env("WRITINGBUDDY_WORDS", app.get_word_count().to_string())
+        .env(
+            "WRITINGBUDDY_SECONDS",
+            app.writing_time.elapsed().as_secs().to_string(),
+        )
+        .stdin(Stdio::null())
+        .status();
+    match status {
+        Ok(status) if !status.success() => {
+            println!("Warning: completion command {command:?} failed with {status}")
+        }
+        Err(err) => println!("Warning: failed to run completion command {command:?}: {err}"),
+        _ => {}
+    }
+}
 
-    // restore terminal
-    disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-    terminal.show_cursor()?;
+/// Stores the entry in the output file
+fn save_entry(path: &Path, app: &App) -> Result<(), WritingBuddyError> {
+    append_entry(path, app).map_err(|source| WritingBuddyError::Save {
+        path: path.display().to_string(),
+        source,
+    })
+}
 
-    if app.has_text() {
-        println!("Storing text into: {}", &filename);
-        let mut output = OpenOptions::new()
-            .write(true)
-            .append(true)
-            .create(true)
-            .open(filename.to_string())
-            .unwrap();
-        if !app.title.is_empty() {
-            writeln!(output, "{}", app.title)?;
+/// Appends the entry to the file or inserts it under the `insert_under` heading
+fn append_entry(path: &Path, app: &App) -> io::Result<()> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    if let Some(start) = app.continued_entry_start {
+        let contents = std::fs::read_to_string(path)?;
+        let before = contents.get(..start).unwrap_or(&contents);
+        let mut entry = Vec::new();
+        write_entry(&mut entry, app)?;
+        let separator = get_entry_separator(before, app.settings.entry_lead_blank);
+        let entry = String::from_utf8_lossy(&entry);
+        return std::fs::write(path, format!("{before}{separator}{entry}"));
+    }
+    if let Some(heading) = &app.settings.insert_under {
+        let contents = std::fs::read_to_string(path).unwrap_or_default();
+        let mut entry = Vec::new();
+        write_entry(&mut entry, app)?;
+        let entry = String::from_utf8_lossy(&entry);
+        if let Some(contents) = insert_under_heading(
+            &contents,
+            heading,
+            &entry,
+            app.settings.output_format,
+            app.settings.entry_lead_blank,
+        ) {
+            return std::fs::write(path, contents);
         }
-        writeln!(output, "{}", app.text)?;
-        writeln!(output)?;
+        println!("Heading {heading:?} not found, appending the text at the end.");
     }
+    let contents = std::fs::read_to_string(path).unwrap_or_default();
+    let mut output = OpenOptions::new().append(true).create(true).open(path)?;
+    write!(
+        output,
+        "{}",
+        get_entry_separator(&contents, app.settings.entry_lead_blank)
+    )?;
+    write_entry(&mut output, app)
+}
 
-    if let Err(err) = res {
-        println!("{:?}", err)
+/// Inserts the entry at the end of the section started by `heading`.
+/// The section ends at the next heading of the same or a higher level.
+fn insert_under_heading(
+    contents: &str,
+    heading: &str,
+    entry: &str,
+    format: OutputFormat,
+    lead_blank: usize,
+) -> Option<String> {
+    let heading = heading.trim();
+    let level = format.heading_level(heading).unwrap_or(usize::MAX);
+    let mut lines = contents.split_inclusive('\n');
+    let mut offset = 0;
+    let mut position = None;
+    for line in lines.by_ref() {
+        offset += line.len();
+        if line.trim_end() == heading {
+            position = Some(offset);
+            break;
+        }
+    }
+    let mut position = position?;
+    for line in lines {
+        if format.heading_level(line).is_some_and(|l| l <= level) {
+            break;
+        }
+        position += line.len();
     }
+    let (before, after) = contents.split_at(position);
+    let separator = get_entry_separator(before, lead_blank);
+    Some(format!("{before}{separator}{entry}{after}"))
+}
 
-    Ok(())
+/// Loads a config file and attaches the path to a failure
+fn load_existing_config_file(
+    config_file: &str,
+    messages: &mut StartupMessages,
+) -> Result<Config, WritingBuddyError> {
+    load_config_file(config_file, messages).map_err(|source| WritingBuddyError::Config {
+        path: config_file.to_string(),
+        source,
+    })
 }
 
-fn get_settings(cli_config: CliConfig) -> Config {
-    if let Some(config_file) = cli_config.config_file {
-        println!("Trying to read specified config file: {:#?}", config_file);
-        let config_builder = Config::builder().add_source(config::File::with_name(&config_file));
-        if let Ok(settings) = config_builder.build() {
-            return settings;
-        } else if cli_config.initialize_config {
-            println!("Trying to create specified config file: {:#?}", config_file);
-            if create_config_file(Path::new(&config_file)) {
-                let config_builder =
-                    Config::builder().add_source(config::File::with_name(&config_file));
-                if let Ok(settings) = config_builder.build() {
-                    return settings;
-                } else {
-                    println!("Failed to read config file that should exist. Exit now");
-                    std::process::exit(1);
-                }
-            }
-        } else {
-            println!("Failed loading specified config file {:?}!", config_file);
-            std::process::exit(1);
-        }
-    } else {
-        println!("Trying to read writingbuddy config file in current directory.");
-        let config_builder = Config::builder().add_source(config::File::with_name("writingbuddy"));
-        if let Ok(settings) = config_builder.build() {
-            return settings;
-        } else {
-            println!("No config file in current directory found.");
-            if cli_config.initialize_config {
-                println!("Trying to create config file in current directory");
-                if create_config_file(Path::new("writingbuddy.toml")) {
-                    let config_builder =
-                        Config::builder().add_source(config::File::with_name("writingbuddy.toml"));
-                    if let Ok(settings) = config_builder.build() {
-                        return settings;
-                    } else {
-                        println!("Failed to read config file that should exist. Exit now");
-                        std::process::exit(1);
-                    }
-                }
-            } else if let Some(config_dir) = dirs::config_dir() {
-                let config_file = config_dir
-                    .join("writingbuddy/writingbuddy")
-                    .to_str()
-                    .unwrap()
-                    .to_string();
-                let config_builder =
-                    Config::builder().add_source(config::File::with_name(&config_file));
-                if let Ok(settings) = config_builder.build() {
-                    return settings;
-                } else {
-                    println!(
-                        "Failed loading config file {:?}! Creating a default config.",
-                        config_file
-                    );
-                    if create_default_config() {
-                        let config_builder =
-                            Config::builder().add_source(config::File::with_name(&config_file));
-                        if let Ok(settings) = config_builder.build() {
-                            return settings;
-                        } else {
-                            println!("Failed to read config file that should exist. Exit now");
-                            std::process::exit(1);
-                        }
-                    }
-                }
+/// Creates a config file with the default settings and loads it
+fn create_and_load_config_file(
+    config_path: &Path,
+    messages: &mut StartupMessages,
+) -> Result<Config, WritingBuddyError> {
+    let path = config_path.display().to_string();
+    if !create_config_file(config_path, messages) {
+        return Err(WritingBuddyError::ConfigNotCreated(path));
+    }
+    load_existing_config_file(&path, messages)
+}
+
+/// Looks for the config file. Every failure is returned so that the
+/// `--on-config-error` policy can decide how to continue.
+fn get_settings(
+    cli_config: &CliConfig,
+    messages: &mut StartupMessages,
+) -> Result<Config, WritingBuddyError> {
+    if let Some(config_file) = &cli_config.config_file {
+        messages.info(format!(
+            "Trying to read specified config file: {:#?}",
+            config_file
+        ));
+        return match load_config_file(config_file, messages) {
+            Ok(settings) => Ok(settings),
+            Err(_) if cli_config.initialize_config => {
+                messages.info(format!(
+                    "Trying to create specified config file: {:#?}",
+                    config_file
+                ));
+                create_and_load_config_file(Path::new(config_file), messages)
             }
-        }
+            Err(source) => Err(WritingBuddyError::Config {
+                path: config_file.clone(),
+                source,
+            }),
+        };
     }
-    Config::default()
+    messages.info("Trying to read writingbuddy config file in current directory.");
+    if let Ok(settings) = load_config_file("writingbuddy", messages) {
+        return Ok(settings);
+    }
+    messages.info("No config file in current directory found.");
+    if cli_config.initialize_config {
+        messages.info("Trying to create config file in current directory");
+        return create_and_load_config_file(Path::new("writingbuddy.toml"), messages);
+    }
+    let config_dir = dirs::config_dir().ok_or(WritingBuddyError::NoConfigDirectory)?;
+    let config_path = config_dir.join("writingbuddy/writingbuddy");
+    let config_file = config_path
+        .to_str()
+        .ok_or_else(|| WritingBuddyError::ConfigPathNotUtf8(config_path.display().to_string()))?
+        .to_string();
+    if let Ok(settings) = load_config_file(&config_file, messages) {
+        return Ok(settings);
+    }
+    messages.notice(format!(
+        "Failed loading config file {:?}! Creating a default config.",
+        config_file
+    ));
+    create_and_load_config_file(&config_dir.join("writingbuddy/writingbuddy.toml"), messages)
+}
+
+/// Asks on the terminal whether to continue with the default settings
+fn confirm_default_settings() -> bool {
+    eprint!("Continue with the default settings? [y/N] ");
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok()
+        && matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Rings the terminal bell `count` times. Terminals without a bell ignore it.
+fn ring_bell(writer: &mut impl Write, count: usize) {
+    let _ = writer.write_all("\x07".repeat(count).as_bytes());
+    let _ = writer.flush();
 }
 
-fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: &mut App) -> io::Result<()> {
+fn run_app<B: Backend + Write>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<()> {
     loop {
         terminal.draw(|f| ui(f, app))?;
 
         if event::poll(Duration::from_millis(200))? {
             if let Event::Key(key) = event::read()? {
+                let was_idle = app.is_idle();
+                app.last_event = Instant::now();
+                if was_idle {
+                    // The key only ends the screensaver
+                    continue;
+                }
+                // The timeout only runs once the first character is typed
+                if app.settings.timeout_resets_on_any_key
+                    && matches!(app.input_mode, InputMode::Writing)
+                    && app.last_keystroke.is_some()
+                {
+                    app.last_keystroke = Some(Instant::now());
+                }
+                if key.code == KeyCode::F(2) {
+                    app.next_theme();
+                    continue;
+                }
+                if key.code == KeyCode::Esc && app.celebration_start.is_some() {
+                    // The first Esc only skips the celebration
+                    app.celebration_start = None;
+                    continue;
+                }
                 match app.input_mode {
                     InputMode::Title => match key.code {
-                        KeyCode::Enter => {
-                            app.writing_time.start();
+                        KeyCode::Enter | KeyCode::Esc | KeyCode::Tab if app.editing_title => {
+                            app.editing_title = false;
+                            app.restart_wpm_window();
                             app.input_mode = InputMode::Writing;
                         }
+                        KeyCode::Enter => app.start_writing(),
+                        KeyCode::Up if app.recent_entries_visible() => app.scroll(-1),
+                        KeyCode::Down if app.recent_entries_visible() => app.scroll(1),
                         KeyCode::Esc => {
-                            return Ok(());
+                            if app.settings.session_note && app.has_text() {
+                                app.input_mode = InputMode::Note;
+                            } else {
+                                return Ok(());
+                            }
                         }
                         KeyCode::Char(c) => {
                             app.title.push(c);
@@ -476,44 +2801,272 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: &mut App) -> io::Res
                         _ => {}
                     },
                     InputMode::Writing => match key.code {
-                        KeyCode::Enter => app.text.push('\n'),
-                        KeyCode::Char(c) => {
-                            app.last_keystroke = Some(Instant::now());
-                            if !app.writing_time.is_running() {
-                                app.writing_time.start();
+                        KeyCode::Enter | KeyCode::Char(_)
+                            if app.reached_char_limit()
+                                && !key.modifiers.contains(KeyModifiers::CONTROL) => {}
+                        KeyCode::Enter => {
+                            if app.settings.timeout_grace_after_paragraph {
+                                app.grace_start = Some(Instant::now());
+                            }
+                            // Shift or Alt inserts a single line break. Not all
+                            // terminals report Shift together with Enter.
+                            let soft_break = key
+                                .modifiers
+                                .intersects(KeyModifiers::SHIFT | KeyModifiers::ALT);
+                            app.scroll_offset = 0;
+                            if app.settings.double_newline_paragraphs && !soft_break {
+                                app.text.push_str("\n\n");
+                            } else {
+                                app.text.push('\n');
                             }
-                            app.text.push(c);
                         }
-                        KeyCode::Backspace => {
-                            if app.backspace_active {
-                                app.text.pop();
+                        KeyCode::Tab => {
+                            // The clock and the keystroke timeout keep running,
+                            // only the words per minute are not measured
+                            app.restart_wpm_window();
+                            app.editing_title = true;
+                            app.input_mode = InputMode::Title;
+                        }
+                        KeyCode::Char('t') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            app.register_keystroke();
+                            let timestamp = Local::now()
+                                .format(&app.settings.timestamp_format)
+                                .to_string();
+                            app.edit_within_char_limit(|app| app.text.push_str(&timestamp));
+                        }
+                        KeyCode::Char('q') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            app.request_strict_off();
+                        }
+                        KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            app.register_keystroke();
+                            app.edit_within_char_limit(App::cycle_heading);
+                        }
+                        KeyCode::Char('s')
+                            if key.modifiers.contains(KeyModifiers::CONTROL) && app.warming_up =>
+                        {
+                            app.set_status_message("The warm-up is not saved".to_string());
+                        }
+                        KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            let message = match app.save_checkpoint() {
+                                Ok(()) => format!("Saved to {}", app.get_checkpoint_file()),
+                                Err(err) => format!("Saving failed: {err}"),
+                            };
+                            app.set_status_message(message);
+                        }
+                        KeyCode::Char(c) => {
+                            app.register_keystroke();
+                            if app.settings.auto_capitalize && app.starts_sentence() {
+                                app.text.extend(c.to_uppercase());
+                            } else {
+                                app.text.push(c);
                             }
+                            app.hard_wrap();
+                            app.record_word_count();
+                        }
+                        KeyCode::Backspace if app.settings.backspace_active => {
+                            app.scroll_offset = 0;
+                            app.text.pop();
+                            app.ratchet_floor = min(app.ratchet_floor, app.text.len());
+                        }
+                        KeyCode::Up if app.can_scroll() => app.scroll(-1),
+                        KeyCode::Down if app.can_scroll() => app.scroll(1),
+                        KeyCode::PageUp if app.can_scroll() => {
+                            let page = app.paragraph_size.get().0.saturating_sub(1).max(1);
+                            app.scroll(-(page as isize));
+                        }
+                        KeyCode::PageDown if app.can_scroll() => {
+                            let page = app.paragraph_size.get().0.saturating_sub(1).max(1);
+                            app.scroll(page as isize);
+                        }
+                        KeyCode::Esc if app.warming_up => {
+                            app.end_warmup();
+                            app.input_mode = InputMode::Title;
+                        }
+                        KeyCode::Esc if app.achieved_goals() || !app.settings.strict_mode => {
+                            app.writing_time.stop();
+                            app.last_keystroke = None;
+                            app.restart_wpm_window();
+                            app.input_mode = InputMode::Title;
+                        }
+                        _ => {}
+                    },
+                    InputMode::Note => match key.code {
+                        KeyCode::Enter => {
+                            return Ok(());
                         }
                         KeyCode::Esc => {
-                            if app.achieved_goals() || !app.strict_mode {
-                                app.writing_time.stop();
-                                app.last_keystroke = None;
-                                app.input_mode = InputMode::Title;
-                            }
+                            app.note.clear();
+                            return Ok(());
+                        }
+                        KeyCode::Char(c) => {
+                            app.note.push(c);
+                        }
+                        KeyCode::Backspace => {
+                            app.note.pop();
                         }
                         _ => {}
                     },
                 }
             }
         }
-        if let (Some(keystroke_timeout), Some(last_keystroke)) =
-            (app.keystroke_timeout, app.last_keystroke)
+        if app.warming_up {
+            // Timeouts and goals only apply to the actual session
+            let warmup = Duration::from_secs(app.settings.warmup_seconds.unwrap_or(0) as u64);
+            if app.writing_time.elapsed() >= warmup {
+                app.end_warmup();
+                app.writing_time.start();
+                app.grace_start = Some(Instant::now());
+                app.set_status_message("Warm-up done. Your session starts now!".to_string());
+            }
+            continue;
+        }
+        if let (Some(keystroke_timeout), Some(idle_time)) =
+            (app.get_keystroke_timeout(), app.get_idle_time())
         {
-            if last_keystroke.elapsed().as_secs() > keystroke_timeout as u64 {
-                app.last_keystroke = None;
-                app.writing_time.reset();
-                app.text.clear();
+            if idle_time.as_secs_f32() > keystroke_timeout {
+                if !app.settings.timeout_confirm {
+                    app.wipe_text();
+                } else if let Some(pending_since) = app.wipe_pending_since {
+                    if pending_since.elapsed() >= WIPE_COUNTDOWN {
+                        app.wipe_text();
+                    }
+                } else {
+                    app.wipe_pending_since = Some(Instant::now());
+                }
+            } else {
+                app.wipe_pending_since = None;
             }
         };
+        if let (InputMode::Writing, Some(min_wpm)) = (&app.input_mode, app.settings.min_wpm) {
+            match app.get_rolling_wpm() {
+                Some(wpm) if wpm < min_wpm as f32 => {
+                    let below_since = *app.wpm_below_since.get_or_insert_with(Instant::now);
+                    if app.settings.wpm_delete_text
+                        && below_since.elapsed().as_secs() > app.settings.wpm_grace_period
+                    {
+                        app.wipe_text();
+                    }
+                }
+                _ => app.wpm_below_since = None,
+            }
+        }
+        if app.settings.goal_sounds {
+            let word_goal_achieved = app.settings.word_goal.is_some() && app.achieved_word_goal();
+            if word_goal_achieved && !app.word_goal_notified {
+                ring_bell(terminal.backend_mut(), 1);
+            }
+            app.word_goal_notified = word_goal_achieved;
+            let time_goal_achieved = app.settings.time_goal.is_some() && app.achieved_time_goal();
+            if time_goal_achieved && !app.time_goal_notified {
+                ring_bell(terminal.backend_mut(), 2);
+            }
+            app.time_goal_notified = time_goal_achieved;
+        }
+        if app.settings.celebration_animation
+            && !app.word_goal_celebrated
+            && app.settings.word_goal.is_some()
+            && app.achieved_word_goal()
+        {
+            app.word_goal_celebrated = true;
+            app.celebration_start = Some(Instant::now());
+        }
+        let celebration_duration = Duration::from_secs(app.settings.celebration_seconds);
+        if app
+            .celebration_start
+            .is_some_and(|start| start.elapsed() >= celebration_duration)
+        {
+            app.celebration_start = None;
+        }
+    }
+}
+
+/// Lines of the celebration animation: sparks rising from the bottom with a
+/// message in the middle
+fn get_celebration_frame(elapsed: Duration, width: usize, height: usize) -> Vec<String> {
+    let frame = (elapsed.as_millis() / CELEBRATION_FRAME.as_millis()) as usize;
+    let message = "Word goal reached!";
+    (0..height)
+        .map(|row| {
+            if row == height / 2 {
+                return format!("{message:^width$}");
+            }
+            // Each frame the sparks climb one row higher
+            if row + frame + 1 < height {
+                return String::new();
+            }
+            (0..width)
+                .map(|col| match (col * 31 + row * 17 + frame * 13) % 11 {
+                    0 => '*',
+                    4 => '+',
+                    8 => '.',
+                    _ => ' ',
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Vertical guide line drawn after a number of columns. Cells that already
+/// contain text are left untouched.
+struct ColumnRuler {
+    column: u16,
+    style: Style,
+}
+
+impl Widget for ColumnRuler {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if self.column >= area.width {
+            return;
+        }
+        let x = area.x + self.column;
+        for y in area.top()..area.bottom() {
+            let cell = buf.get_mut(x, y);
+            if cell.symbol == " " {
+                cell.set_symbol("│").set_style(self.style);
+            }
+        }
     }
 }
 
+/// Rect of the given size centered inside `area`
+fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
 fn ui<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let theme = app.get_theme();
+    let (min_width, min_height) = app.settings.min_terminal_size;
+    let size = f.size();
+    if size.width < min_width || size.height < min_height {
+        let message = format!(
+            "Terminal too small (need {min_width}x{min_height}, have {}x{})",
+            size.width, size.height
+        );
+        let message = Paragraph::new(message)
+            .style(app.get_style(theme.warning))
+            .wrap(Wrap { trim: true });
+        f.render_widget(message, size);
+        return;
+    }
+
+    if app.is_idle() {
+        let area = centered_rect(size.width.saturating_sub(8), 5, size);
+        let quote = Paragraph::new(app.get_idle_quote())
+            .style(app.get_style(theme.passive))
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true });
+        f.render_widget(quote, area);
+        return;
+    }
+
+    let stats_height = if app.stats_visible() { 3 } else { 0 };
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(2)
@@ -522,7 +3075,7 @@ fn ui<B: Backend>(f: &mut Frame<B>, app: &App) {
                 Constraint::Length(3),
                 Constraint::Length(3),
                 Constraint::Min(1),
-                Constraint::Length(3),
+                Constraint::Length(stats_height),
             ]
             .as_ref(),
         )
@@ -530,79 +3083,453 @@ fn ui<B: Backend>(f: &mut Frame<B>, app: &App) {
 
     let text = Text::from(Spans::from(app.get_instruction()));
     let help_message = Paragraph::new(text)
-        .style(Style::default().fg(PASSIVE_COLOR))
+        .style(app.get_style(theme.passive))
         .block(Block::default().borders(Borders::ALL).title("Instructions"));
     f.render_widget(help_message, chunks[0]);
 
     let widget_colors = app.get_widget_colors();
 
     let title = Paragraph::new(app.title.clone())
-        .style(Style::default().fg(widget_colors.0))
+        .style(app.get_style(widget_colors.0))
         .block(Block::default().borders(Borders::ALL).title("Title"));
     f.render_widget(title, chunks[1]);
 
     let paragraph_cols = max(6, f.size().width as usize) - 6; // subtract 6 for border
     let paragraph_rows = max(2, chunks[2].height as usize) - 2; // subtract 2 for border
 
+    app.paragraph_size.set((paragraph_rows, paragraph_cols));
     let wrapped_text = app.get_paragraph_text(paragraph_rows, paragraph_cols);
 
     match app.input_mode {
         InputMode::Title => {
             f.set_cursor(
                 // Put cursor past the end of the title text
-                chunks[1].x + app.title.chars().count() as u16 + 1,
+                chunks[1].x + app.title.width() as u16 + 1,
                 // Move one line down, from the border to the title line
                 chunks[1].y + 1,
             )
         }
+        // The cursor is hidden while the end of the text is scrolled out of view
+        InputMode::Writing if app.scroll_offset > 0 => {}
         InputMode::Writing => {
             let text_position = get_text_position(&wrapped_text);
+            let line_offset = get_line_offset(
+                app.settings.text_align,
+                text_position.0,
+                chunks[2].width.saturating_sub(2),
+            );
             f.set_cursor(
                 // Put cursor past the end of the input text
-                chunks[2].x + 1 + text_position.0,
+                chunks[2].x + 1 + line_offset + text_position.0,
                 // Move one line down, from the border to the input line
                 chunks[2].y + text_position.1,
             )
         }
+        InputMode::Note => {}
     }
 
-    let text = Paragraph::new(wrapped_text)
-        .style(Style::default().fg(widget_colors.1))
-        .block(Block::default().borders(Borders::ALL).title("Text"));
+    let line_warnings = app.get_line_warnings(paragraph_rows, paragraph_cols);
+    let lines: Vec<Spans> = wrapped_text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| match line_warnings.get(index) {
+            Some(true) => Spans::from(Span::styled(line, app.get_style(theme.warning))),
+            _ => Spans::from(line),
+        })
+        .collect();
+    let text = Paragraph::new(lines)
+        .alignment(app.settings.text_align)
+        .style(app.get_style(widget_colors.1))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(match app.get_timeout_warning() {
+                    Some(warning) => format!("Text - {warning}"),
+                    None => "Text".to_string(),
+                }),
+        );
     f.render_widget(text, chunks[2]);
+    if let Some(column) = app.settings.ruler_column {
+        let ruler = ColumnRuler {
+            column: column.try_into().unwrap_or(u16::MAX),
+            style: app.get_style(theme.passive).add_modifier(Modifier::DIM),
+        };
+        f.render_widget(
+            ruler,
+            Block::default().borders(Borders::ALL).inner(chunks[2]),
+        );
+    }
 
-    let stat_chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints(
-            [
-                Constraint::Percentage(34),
-                Constraint::Percentage(33),
-                Constraint::Percentage(33),
-            ]
-            .as_ref(),
-        )
-        .split(chunks[3]);
-    {
-        let stats = Paragraph::new(app.get_word_count_string())
-            .style(Style::default().fg(app.get_word_count_color()))
-            .block(Block::default().borders(Borders::ALL).title("Word count"))
-            .wrap(Wrap { trim: true });
-        f.render_widget(stats, stat_chunks[0]);
+    if app.recent_entries_visible() {
+        let wrapped_entries = textwrap::fill(&app.recent_entries, get_wrap_options(paragraph_cols));
+        let total_lines = wrapped_entries.lines().count();
+        let skip = app.get_first_visible_line(total_lines, paragraph_rows);
+        let lines: Vec<Spans> = wrapped_entries
+            .lines()
+            .skip(skip)
+            .map(Spans::from)
+            .collect();
+        let recent_entries = Paragraph::new(lines)
+            .style(app.get_style(theme.passive).add_modifier(Modifier::DIM))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Recent entries"),
+            );
+        f.render_widget(Clear, chunks[2]);
+        f.render_widget(recent_entries, chunks[2]);
+    }
+
+    if app.stats_visible() {
+        let stats = app.get_stats();
+        let constraints: Vec<Constraint> = stats
+            .iter()
+            .map(|_| Constraint::Ratio(1, stats.len() as u32))
+            .collect();
+        let stat_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(constraints)
+            .split(chunks[3]);
+        for ((title, value, color), chunk) in stats.into_iter().zip(stat_chunks) {
+            let stats = Paragraph::new(value)
+                .style(app.get_style(color))
+                .block(Block::default().borders(Borders::ALL).title(title))
+                .wrap(Wrap { trim: true });
+            f.render_widget(stats, chunk);
+        }
+    }
+
+    if let Some(pending_since) = app.wipe_pending_since {
+        let remaining = WIPE_COUNTDOWN.saturating_sub(pending_since.elapsed());
+        let area = centered_rect(chunks[2].width.saturating_sub(4), 3, chunks[2]);
+        let countdown = Paragraph::new(format!(
+            "Resume typing! Deleting your text in {}…",
+            remaining.as_secs() + 1
+        ))
+        .style(app.get_style(theme.danger))
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL));
+        f.render_widget(Clear, area);
+        f.render_widget(countdown, area);
+    }
 
-        let stats = Paragraph::new(app.get_character_count_string())
-            .style(Style::default().fg(app.get_character_count_color()))
+    if let Some(start) = app.celebration_start {
+        let area = centered_rect(chunks[2].width.saturating_sub(4), 7, chunks[2]);
+        let inner = Block::default().borders(Borders::ALL).inner(area);
+        let frame =
+            get_celebration_frame(start.elapsed(), inner.width as usize, inner.height as usize);
+        let celebration = Paragraph::new(frame.join("\n"))
+            .style(app.get_style(theme.done))
             .block(
                 Block::default()
                     .borders(Borders::ALL)
-                    .title("Character count"),
-            )
-            .wrap(Wrap { trim: true });
-        f.render_widget(stats, stat_chunks[1]);
+                    .title("Press [Esc] to skip"),
+            );
+        f.render_widget(Clear, area);
+        f.render_widget(celebration, area);
+    }
 
-        let stats = Paragraph::new(app.get_time_string())
-            .style(Style::default().fg(app.get_time_color()))
-            .block(Block::default().borders(Borders::ALL).title("Time"))
-            .wrap(Wrap { trim: true });
-        f.render_widget(stats, stat_chunks[2]);
+    if let InputMode::Note = app.input_mode {
+        let area = centered_rect(chunks[2].width.saturating_sub(4), 3, chunks[2]);
+        let note = Paragraph::new(app.note.clone())
+            .style(app.get_style(theme.active))
+            .block(Block::default().borders(Borders::ALL).title("Session note"));
+        f.render_widget(Clear, area);
+        f.render_widget(note, area);
+        f.set_cursor(area.x + app.note.width() as u16 + 1, area.y + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_app(config: Config) -> App {
+        App::new(
+            "## Title".to_string(),
+            "test.md".to_string(),
+            Settings::from_config(&config),
+        )
+    }
+
+    fn config_with(key: &str, value: i64) -> Config {
+        Config::builder()
+            .set_override(key, value)
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn cycle_heading_moves_ratchet_floor() {
+        let mut app = test_app(config_with("ratchet_step", 1));
+        app.text = "ab é ".to_string();
+        app.record_word_count();
+        assert_eq!(app.ratchet_floor, app.text.len());
+        app.cycle_heading();
+        assert_eq!(app.text, "### ab é ");
+        assert_eq!(app.ratchet_floor, app.text.len());
+        app.text.push('x');
+        app.record_word_count();
+        app.wipe_text();
+        assert_eq!(app.text, "### ab é ");
+    }
+
+    #[test]
+    fn rolling_wpm_drops_after_a_pause() {
+        let mut app = test_app(config_with("wpm_window", 1));
+        app.start_writing();
+        app.text = "one two three four five".to_string();
+        app.record_word_count();
+        std::thread::sleep(Duration::from_millis(2100));
+        assert_eq!(app.get_rolling_wpm(), Some(0.0));
+    }
+
+    #[test]
+    fn rolling_wpm_waits_for_a_full_window_after_a_wipe() {
+        let config = Config::builder()
+            .set_override("wpm_window", 1)
+            .unwrap()
+            .set_override("reset_time_on_timeout", false)
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut app = test_app(config);
+        app.start_writing();
+        std::thread::sleep(Duration::from_millis(1100));
+        assert!(app.get_rolling_wpm().is_some());
+        app.wipe_text();
+        assert_eq!(app.get_rolling_wpm(), None);
+    }
+
+    #[test]
+    fn piped_text_survives_the_warmup() {
+        let mut app = test_app(config_with("warmup_seconds", 60));
+        app.load_existing_text("draft text\r\n");
+        app.text.push_str("warm-up");
+        app.end_warmup();
+        assert_eq!(app.text, "draft text");
+        assert_eq!(app.get_goal_word_count(), 0);
+    }
+
+    #[test]
+    fn csv_row_escapes_the_title() {
+        assert_eq!(
+            format_csv_row("2024-01-02", "plain", 120, 60, true),
+            "2024-01-02,plain,120,60,120.0,true"
+        );
+        assert_eq!(
+            format_csv_row("2024-01-02", "say \"hi\", then\nleave", 0, 0, false),
+            "2024-01-02,\"say \"\"hi\"\", then\nleave\",0,0,0.0,false"
+        );
+    }
+
+    #[test]
+    fn csv_header_is_written_once_on_create() {
+        let directory =
+            std::env::temp_dir().join(format!("writingbuddy-csv-{}", std::process::id()));
+        let path = directory.join("log.csv");
+        let _ = std::fs::remove_dir_all(&directory);
+        append_csv_row(&path, "first").unwrap();
+        append_csv_row(&path, "second").unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(contents, format!("{CSV_HEADER}\nfirst\nsecond\n"));
+    }
+
+    #[test]
+    fn remaining_words_follow_the_project_goal() {
+        let config = Config::builder()
+            .set_override("word_goal", 100)
+            .unwrap()
+            .set_override("project_goal", 1000)
+            .unwrap()
+            .set_override("project_goal_counts", true)
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut app = test_app(config);
+        app.project_words = Some(850);
+        app.text = "word ".repeat(200);
+        assert_eq!(app.get_remaining_words(), Some(-50));
+        assert!(app.achieved_word_goal());
+        assert_eq!(app.get_remaining_goals_string(), "");
+        app.text = "word ".repeat(50);
+        assert_eq!(app.get_remaining_goals_string(), "100 words");
+    }
+
+    #[test]
+    fn split_last_entry_takes_the_last_title_heading() {
+        let contents = "## Monday\nfirst\n### Part\nmore\n## Tuesday\nsecond\n\n";
+        let settings = Settings::from_config(&Config::default());
+        let (start, title, text) = split_last_entry(contents, &settings, "## Title").unwrap();
+        assert_eq!(&contents[start..], "## Tuesday\nsecond\n\n");
+        assert_eq!(title, "## Tuesday");
+        assert_eq!(text, "second");
+        assert!(split_last_entry("no heading\n", &settings, "## Title").is_none());
+    }
+
+    #[test]
+    fn continued_entry_is_replaced_on_save() {
+        let directory =
+            std::env::temp_dir().join(format!("writingbuddy-continue-{}", std::process::id()));
+        let path = directory.join("draft.md");
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        let contents = "## Monday\nfirst\n\n## Tuesday\nsecond\n";
+        std::fs::write(&path, contents).unwrap();
+        let mut app = test_app(Config::default());
+        let (start, title, text) = split_last_entry(contents, &app.settings, "## Title").unwrap();
+        app.title = title;
+        app.continued_entry_start = Some(start);
+        app.load_existing_text(&text);
+        app.text.push_str(" and more");
+        append_entry(&path, &app).unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(saved, "## Monday\nfirst\n\n## Tuesday\nsecond and more\n\n");
+    }
+
+    #[test]
+    fn continued_entry_drops_the_old_badge_and_note() {
+        let config = Config::builder()
+            .set_override("record_goal_badge", true)
+            .unwrap()
+            .set_override("word_goal", 2)
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut app = test_app(config);
+        let contents = "## Tuesday\nsecond day\n(goal: 2 words ✓ in 5 min)\n<!-- felt good -->\n\n";
+        let (_, _, text) = split_last_entry(contents, &app.settings, "## Title").unwrap();
+        assert_eq!(text, "second day");
+        app.load_existing_text(&text);
+        app.text.push_str(" and more");
+        app.note = "again".to_string();
+        let mut entry = Vec::new();
+        write_entry(&mut entry, &app).unwrap();
+        let entry = String::from_utf8(entry).unwrap();
+        assert_eq!(entry.matches("(goal: ").count(), 1);
+        assert_eq!(entry.matches("<!--").count(), 1);
+        assert!(!entry.contains("felt good"));
+    }
+
+    #[test]
+    fn insert_under_heading_at_the_end_without_line_break() {
+        let contents = "# Day\n## Notes";
+        let inserted =
+            insert_under_heading(contents, "## Notes", "entry\n", OutputFormat::Markdown, 1);
+        assert_eq!(inserted.unwrap(), "# Day\n## Notes\n\nentry\n");
+    }
+
+    #[test]
+    fn insert_under_heading_stops_at_a_higher_level_heading() {
+        let contents = "# Day\n## Notes\nold\n### Detail\nmore\n# Next\n";
+        let inserted =
+            insert_under_heading(contents, "## Notes", "entry\n", OutputFormat::Markdown, 0);
+        assert_eq!(
+            inserted.unwrap(),
+            "# Day\n## Notes\nold\n### Detail\nmore\nentry\n# Next\n"
+        );
+        assert!(
+            insert_under_heading(contents, "## Other", "entry\n", OutputFormat::Markdown, 0)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn entry_separator_counts_existing_blank_lines() {
+        assert_eq!(get_entry_separator("", 1), "");
+        assert_eq!(get_entry_separator("text", 1), "\n\n");
+        assert_eq!(get_entry_separator("text\n", 1), "\n");
+        assert_eq!(get_entry_separator("text\n\n", 1), "");
+        assert_eq!(get_entry_separator("text\n\n\n", 1), "");
+        assert_eq!(get_entry_separator("text\n", 0), "");
+    }
+
+    #[test]
+    fn glob_matches_wildcards() {
+        assert!(matches_glob("draft.md", "*"));
+        assert!(matches_glob("draft.md", "draft*"));
+        assert!(matches_glob("draft.md", "*.md"));
+        assert!(matches_glob("draft.md", "dr?ft.md"));
+        assert!(!matches_glob("draft.txt", "*.md"));
+        assert!(!matches_glob("draft.md", "dr?ft"));
+    }
+
+    #[test]
+    fn file_period_is_parsed_from_the_name() {
+        assert_eq!(
+            parse_file_period("2023-04-05.md", "%Y-%m-%d.md"),
+            Some((2023, 4, 5))
+        );
+        assert_eq!(
+            parse_file_period("2023-04.md", "%Y-%m.md"),
+            Some((2023, 4, 1))
+        );
+        assert_eq!(parse_file_period("notes.md", "%Y-%m.md"), None);
+    }
+
+    #[test]
+    fn words_are_counted_per_mode() {
+        let text = "# Heading\n- item one\n1. [link](https://example.com) text\n> - quoted";
+        assert_eq!(count_words(text, WordCountMode::Plain), 11);
+        assert_eq!(count_words(text, WordCountMode::Markdown), 6);
+        assert_eq!(count_words(text, WordCountMode::Auto), 6);
+        assert_eq!(count_words("just - plain text", WordCountMode::Auto), 4);
+        assert_eq!(count_words("  just - plain text", WordCountMode::Auto), 4);
+    }
+
+    #[test]
+    fn markdown_prefixes_are_stripped() {
+        assert_eq!(strip_markdown_prefix("## Heading"), "Heading");
+        assert_eq!(strip_markdown_prefix("  > - quoted item"), "quoted item");
+        assert_eq!(strip_markdown_prefix("12. numbered"), "numbered");
+        assert_eq!(strip_markdown_prefix("#hashtag"), "#hashtag");
+        assert_eq!(strip_markdown_prefix("3.14 is pi"), "3.14 is pi");
+    }
+
+    #[test]
+    fn restarting_the_wpm_window_forgets_slow_writing() {
+        let mut app = test_app(config_with("min_wpm", 10));
+        app.record_word_count();
+        app.wpm_window_start -= Duration::from_secs(120);
+        app.wpm_below_since = Some(Instant::now() - Duration::from_secs(120));
+        app.restart_wpm_window();
+        assert!(app.get_rolling_wpm().is_none());
+        assert!(app.wpm_below_since.is_none());
+        assert!(app.word_samples.is_empty());
+    }
+
+    #[test]
+    fn edits_past_a_blocking_char_limit_are_undone() {
+        let config = Config::builder()
+            .set_override("char_limit", 6)
+            .unwrap()
+            .set_override("char_limit_blocks_input", true)
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut app = test_app(config);
+        app.text = "words".to_string();
+        app.edit_within_char_limit(App::cycle_heading);
+        assert_eq!(app.text, "words");
+        app.edit_within_char_limit(|app| app.text.push('!'));
+        assert_eq!(app.text, "words!");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn project_words_skip_checkpoints_and_linked_directories() {
+        let directory =
+            std::env::temp_dir().join(format!("writingbuddy-project-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(directory.join("part")).unwrap();
+        std::fs::write(directory.join("part/one.md"), "two words\n").unwrap();
+        std::fs::write(directory.join("part/one.md.checkpoint"), "not counted\n").unwrap();
+        std::os::unix::fs::symlink(&directory, directory.join("part/up")).unwrap();
+        let words = count_project_words(&directory, "*", OutputFormat::Markdown);
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(words, 2);
     }
 }